@@ -1,7 +1,6 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
-use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex, Weak};
 
 const SELF_CLOSING_TAGS: [&str; 14] = [
     "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
@@ -13,14 +12,14 @@ const HEAD_TAGS: [&str; 9] = [
 
 pub struct TreeNode {
     pub value: Element,
-    pub children: Vec<Rc<RefCell<TreeNode>>>,
-    pub parent: Option<Weak<RefCell<TreeNode>>>,
+    pub children: Vec<Arc<Mutex<TreeNode>>>,
+    pub parent: Option<Weak<Mutex<TreeNode>>>,
 }
 
 impl TreeNode {
     /** Create a new Tree Node */
-    fn new(value: Element, parent: Option<Weak<RefCell<TreeNode>>>) -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(TreeNode {
+    fn new(value: Element, parent: Option<Weak<Mutex<TreeNode>>>) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(TreeNode {
             value,
             children: Vec::new(),
             parent,
@@ -28,13 +27,13 @@ impl TreeNode {
     }
 
     /** Add a child to the node */
-    fn add_child(&mut self, child: Rc<RefCell<Self>>) {
+    fn add_child(&mut self, child: Arc<Mutex<Self>>) {
         // Set the parent reference
-        let weak_self = Rc::downgrade(&Rc::clone(&child));
-        child.try_borrow_mut().unwrap().parent = Some(weak_self);
+        let weak_self = Arc::downgrade(&Arc::clone(&child));
+        child.lock().unwrap().parent = Some(weak_self);
 
         // Push the child into the parent's children vector
-        self.children.push(Rc::clone(&child));
+        self.children.push(Arc::clone(&child));
     }
 }
 pub struct Tag {
@@ -45,10 +44,16 @@ pub enum Element {
     Tag(Tag),
     Text(String),
 }
-/** HTML Parser class. */
+/**
+ HTML Parser class. Markup can be parsed all at once with `parse`, or fed in as it arrives,
+ in which case the tree built so far is available from `root` and grows as more comes in.
+*/
 pub struct HTMLParser {
     body: String,
-    unfinished: Vec<Rc<RefCell<TreeNode>>>,
+    unfinished: Vec<Arc<Mutex<TreeNode>>>,
+    /** Text or tag contents seen since the last `<` or `>`. */
+    buffer: String,
+    in_tag: bool,
 }
 impl HTMLParser {
     /** Creates an object for parsing */
@@ -56,6 +61,8 @@ impl HTMLParser {
         Self {
             body,
             unfinished: Vec::new(),
+            buffer: String::new(),
+            in_tag: false,
         }
     }
     /** Add text node to the DOM */
@@ -65,8 +72,8 @@ impl HTMLParser {
         }
         self.implicit_tags(None);
         let parent = self.unfinished.last_mut().unwrap();
-        let node = TreeNode::new(Element::Text(text), Some(Rc::downgrade(&parent)));
-        parent.try_borrow_mut().unwrap().add_child(node);
+        let node = TreeNode::new(Element::Text(text), Some(Arc::downgrade(parent)));
+        parent.lock().unwrap().add_child(node);
     }
     /** Add tag node to the DOM */
     fn add_tag(&mut self, text: String) {
@@ -82,36 +89,27 @@ impl HTMLParser {
             if self.unfinished.len() == 1 {
                 return;
             }
-            let node = self.unfinished.pop().unwrap();
-            let mut parent = self
-                .unfinished
-                .last_mut()
-                .unwrap()
-                .try_borrow_mut()
-                .unwrap();
-            parent.add_child(node);
+            // Nodes are attached to their parent when opened, so closing only pops them.
+            self.unfinished.pop();
         } else if SELF_CLOSING_TAGS.contains(&tag.as_str()) {
             let parent = self.unfinished.last_mut();
             let node = TreeNode::new(
                 Element::Tag(Tag { tag, attributes }),
-                match &parent {
-                    Some(parent) => Some(Rc::downgrade(&parent)),
-                    None => None,
-                },
+                parent.as_ref().map(|parent| Arc::downgrade(parent)),
             );
             match parent {
-                Some(parent) => parent.try_borrow_mut().unwrap().add_child(node),
+                Some(parent) => parent.lock().unwrap().add_child(node),
                 None => unreachable!(),
             };
         } else {
             let parent = self.unfinished.last_mut();
             let node = TreeNode::new(
                 Element::Tag(Tag { tag, attributes }),
-                match parent {
-                    Some(parent) => Some(Rc::downgrade(&parent)),
-                    None => None,
-                },
+                parent.as_ref().map(|parent| Arc::downgrade(parent)),
             );
+            if let Some(parent) = parent {
+                parent.lock().unwrap().add_child(Arc::clone(&node));
+            }
             self.unfinished.push(node);
         }
     }
@@ -121,27 +119,27 @@ impl HTMLParser {
         let open_tags: Vec<String> = self
             .unfinished
             .iter()
-            .filter_map(|node| match &node.borrow().value {
+            .filter_map(|node| match &node.lock().unwrap().value {
                 Element::Tag(tag) => Some(tag.tag.to_owned()),
                 _ => None,
             })
             .collect();
 
-        if let Some(tag) = tag {
-            if open_tags.is_empty() && tag != "html" {
-                implicit_tags_to_add.push("html");
-            } else if open_tags == ["html"] && !["head", "body", "/html"].contains(&tag) {
-                if Self::is_head_tag(tag) {
-                    implicit_tags_to_add.push("head");
-                } else {
-                    implicit_tags_to_add.push("body");
-                }
-            } else if open_tags == ["html", "head"]
-                && !["/head"].contains(&tag)
-                && !Self::is_head_tag(tag)
-            {
-                implicit_tags_to_add.push("/head");
+        // Text needs the same implicit tags as any element that belongs in the body.
+        let tag = tag.unwrap_or_default();
+        if open_tags.is_empty() && tag != "html" {
+            implicit_tags_to_add.push("html");
+        } else if open_tags == ["html"] && !["head", "body", "/html"].contains(&tag) {
+            if Self::is_head_tag(tag) {
+                implicit_tags_to_add.push("head");
+            } else {
+                implicit_tags_to_add.push("body");
             }
+        } else if open_tags == ["html", "head"]
+            && !["/head"].contains(&tag)
+            && !Self::is_head_tag(tag)
+        {
+            implicit_tags_to_add.push("/head");
         }
 
         for tag in implicit_tags_to_add {
@@ -150,10 +148,7 @@ impl HTMLParser {
     }
 
     fn is_head_tag(tag: &str) -> bool {
-        [
-            "base", "basefont", "bgsound", "noscript", "link", "meta", "title", "style", "script",
-        ]
-        .contains(&tag)
+        HEAD_TAGS.contains(&tag)
     }
     /** Get the attributes of a tag. */
     fn get_attributes(&self, text: String) -> (String, HashMap<String, String>) {
@@ -166,7 +161,7 @@ impl HTMLParser {
         for attrpair in &parts[1..parts.len()] {
             if attrpair.contains("=") {
                 let (key, mut value) = attrpair.split_once("=").unwrap();
-                if value.len() > 2 && ["'", "\""].contains(&value.split_at(0).0) {
+                if value.len() >= 2 && ["'", "\""].contains(&value.split_at(1).0) {
                     value = &value[1..value.len() - 1];
                 }
                 attributes.insert(key.to_lowercase(), value.to_string());
@@ -177,46 +172,55 @@ impl HTMLParser {
         (tag, attributes)
     }
     /** finish parsing and return the root node */
-    fn finish(&mut self) -> Rc<RefCell<TreeNode>> {
+    pub fn finish(&mut self) -> Arc<Mutex<TreeNode>> {
+        if !self.in_tag && !self.buffer.is_empty() {
+            let text = std::mem::take(&mut self.buffer);
+            self.add_text(text);
+        }
         if self.unfinished.is_empty() {
             self.implicit_tags(None);
         }
-        while self.unfinished.len() > 1 {
-            let node = self.unfinished.pop().unwrap();
-            let mut parent = self
-                .unfinished
-                .last_mut()
-                .unwrap()
-                .try_borrow_mut()
-                .unwrap();
-            parent.add_child(node);
-        }
+        self.unfinished.truncate(1);
         self.unfinished.pop().unwrap()
     }
-    /** Parse function. Parses the body of the object and returns the root node.*/
-    pub fn parse(&mut self) -> Rc<RefCell<TreeNode>> {
-        let mut buffer = "".to_string();
-        let mut in_tag = false;
-        let chars = self.body.clone();
-        let chars = chars.chars();
-        for c in chars {
+    /** The root of the tree parsed so far, once anything has been added to it. */
+    pub fn root(&self) -> Option<Arc<Mutex<TreeNode>>> {
+        self.unfinished.first().cloned()
+    }
+    /** Find the `href` of the document's first `<base>` element, if any. */
+    pub fn base_href(node: &Arc<Mutex<TreeNode>>) -> Option<String> {
+        let node = node.lock().unwrap();
+        if let Element::Tag(tag) = &node.value {
+            if tag.tag == "base" {
+                if let Some(href) = tag.attributes.get("href") {
+                    return Some(href.to_owned());
+                }
+            }
+        }
+        node.children.iter().find_map(Self::base_href)
+    }
+    /** Parse the next piece of markup. Text is held back until the tag after it starts. */
+    pub fn feed(&mut self, markup: &str) {
+        for c in markup.chars() {
             if c == '<' {
-                in_tag = true;
-                if !buffer.is_empty() {
-                    self.add_text(buffer);
+                self.in_tag = true;
+                if !self.buffer.is_empty() {
+                    let text = std::mem::take(&mut self.buffer);
+                    self.add_text(text);
                 }
-                buffer = "".to_string();
             } else if c == '>' {
-                in_tag = false;
-                self.add_tag(buffer);
-                buffer = "".to_string();
+                self.in_tag = false;
+                let tag = std::mem::take(&mut self.buffer);
+                self.add_tag(tag);
             } else {
-                buffer = format!("{}{}", buffer, c);
+                self.buffer.push(c);
             }
         }
-        if !in_tag && !buffer.is_empty() {
-            self.add_text(buffer);
-        }
+    }
+    /** Parse function. Parses the body of the object and returns the root node.*/
+    pub fn parse(&mut self) -> Arc<Mutex<TreeNode>> {
+        let body = std::mem::take(&mut self.body);
+        self.feed(&body);
         self.finish()
     }
 }
@@ -226,16 +230,14 @@ fn print_tree(node: &TreeNode, f: &mut fmt::Formatter<'_>, indent: i32) -> fmt::
     }
     let _ = match &node.value {
         Element::Tag(t) => {
-            write!(f, "<{}>\n", t.tag)
+            writeln!(f, "<{}>", t.tag)
         }
         Element::Text(t) => {
-            write!(f, "{}\n", t)
+            writeln!(f, "{}", t)
         }
     };
     for children in &node.children {
-        let _ = children
-            .try_borrow_mut()
-            .inspect(|c| print_tree(c, f, indent + 1).unwrap());
+        print_tree(&children.lock().unwrap(), f, indent + 1)?;
     }
     Ok(())
 }
@@ -1,6 +1,9 @@
 use crate::dom::TreeNode;
 use macroquad::prelude::*;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 pub struct DefaultFont {
     roman: Font,
@@ -10,7 +13,7 @@ pub struct DefaultFont {
     mono: Font,
     bold_mono: Font,
 }
-impl<'a> Default for DefaultFont {
+impl Default for DefaultFont {
     fn default() -> Self {
         let mut roman =
             load_ttf_font_from_bytes(include_bytes!("assets/fonts/OpenSans/OpenSans-Regular.ttf"))
@@ -159,9 +162,10 @@ impl<'a> Layout<'a> {
         &mut self,
         font: &'a DefaultFont,
         cache: &mut HashMap<String, TextDimensions>,
-        node: &Rc<RefCell<TreeNode>>,
+        node: &Arc<Mutex<TreeNode>>,
     ) {
-        match &node.try_borrow().unwrap().value {
+        let node = node.lock().unwrap();
+        match &node.value {
             crate::dom::Element::Text(text) => {
                 if !self.print {
                     return;
@@ -187,7 +191,7 @@ impl<'a> Layout<'a> {
             }
             crate::dom::Element::Tag(tag) => {
                 self.open_tag(&tag.tag);
-                for child in &node.try_borrow().unwrap().children {
+                for child in &node.children {
                     self.recurse(font, cache, child);
                 }
                 self.close_tag(&tag.tag);
@@ -198,10 +202,11 @@ impl<'a> Layout<'a> {
         &mut self,
         font: &'a DefaultFont,
         cache: &mut HashMap<String, TextDimensions>,
-        node: &Rc<RefCell<TreeNode>>,
+        node: &Arc<Mutex<TreeNode>>,
         indent: u32,
     ) {
-        match &node.try_borrow().unwrap().value {
+        let node = node.lock().unwrap();
+        match &node.value {
             crate::dom::Element::Text(text) => {
                 for word in text.split_whitespace() {
                     self.word(&font.roman, cache, word, indent + 1, None);
@@ -209,28 +214,28 @@ impl<'a> Layout<'a> {
                 self.flush();
             }
             crate::dom::Element::Tag(tag) => {
-                self.word(&font.bold, cache, format!("<").as_str(), indent, None);
+                self.word(&font.bold, cache, "<", indent, None);
                 self.word(
                     &font.bold,
                     cache,
-                    format!("{}", &tag.tag).as_str(),
+                    &tag.tag,
                     indent,
                     Some(Color::from_hex(0xf55e5e)),
                 );
-                self.word(&font.bold, cache, format!(">").as_str(), indent, None);
+                self.word(&font.bold, cache, ">", indent, None);
                 self.flush();
-                for child in &node.try_borrow().unwrap().children {
+                for child in &node.children {
                     self.recurse_source(font, cache, child, indent + 2);
                 }
-                self.word(&font.bold, cache, format!("</").as_str(), indent, None);
+                self.word(&font.bold, cache, "</", indent, None);
                 self.word(
                     &font.bold,
                     cache,
-                    format!("{}", &tag.tag).as_str(),
+                    &tag.tag,
                     indent,
                     Some(Color::from_hex(0xf55e5e)),
                 );
-                self.word(&font.bold, cache, format!(">").as_str(), indent, None);
+                self.word(&font.bold, cache, ">", indent, None);
                 self.flush();
             }
         }
@@ -295,7 +300,7 @@ impl<'a> Layout<'a> {
     pub fn layout(
         &mut self,
         cache: &mut HashMap<String, TextDimensions>,
-        node: &Rc<RefCell<TreeNode>>,
+        node: &Arc<Mutex<TreeNode>>,
         font: &'a DefaultFont,
     ) {
         self.reset();
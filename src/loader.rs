@@ -0,0 +1,377 @@
+use crate::dom::{HTMLParser, TreeNode};
+use crate::networking;
+use crate::networking::auth::{self, Credentials, Prompt};
+use crate::networking::charset::{self, TextDecoder};
+use crate::networking::download::{self, Saving};
+use crate::networking::error::NetworkError;
+use crate::networking::progress::{self, Phase, Progress, Stream};
+use crate::networking::request::{Body, Method};
+use crate::networking::response::Response;
+use crate::networking::tls::Certificate;
+use crate::networking::url::URL;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/** Turn a response into markup for the parser, based on its status and content type. */
+fn document(response: &Response) -> String {
+    if !response.is_success() {
+        eprintln!("{} {}", response.status, response.reason);
+    }
+    match response.content_type().as_deref() {
+        None | Some("text/html") | Some("application/xhtml+xml") => response.text(),
+        Some(_) if charset::is_text(response.headers.get("content-type")) => {
+            format!("<pre>{}</pre>", html_escape::encode_text(&response.text()))
+        }
+        Some(mime) => format!("<p>Cannot display content of type {}</p>", mime),
+    }
+}
+
+/** A summary of the connection's security, for the console until the UI has an indicator. */
+fn security_summary(certificate: &Certificate) -> String {
+    let state = if !certificate.verified {
+        "NOT VERIFIED (insecure mode)"
+    } else if certificate.is_expired() {
+        "certificate expired"
+    } else {
+        "secure"
+    };
+    format!(
+        "Connection {}\n  subject {}\n  issuer {}\n  valid until {}\n  SHA-256 {}",
+        state,
+        certificate.subject,
+        certificate.issuer,
+        networking::date::format(certificate.not_after),
+        certificate.fingerprint
+    )
+}
+
+/**
+ Send a `method` request for `url`, returning the final response. Opening a download that
+ stopped partway asks for the rest of it.
+*/
+fn load(url: &URL, method: Method, body: Option<Body>) -> Result<Response, NetworkError> {
+    let headers = match (method, &body) {
+        (Method::Get, None) => download::resume_headers(&url.to_string()),
+        _ => Vec::new(),
+    };
+    let response = url.request(method, body, &headers)?;
+    networking::about::record_visit(&response.url);
+    println!(
+        "Loaded {} ({} bytes, {} decoded)",
+        response.url,
+        response.encoded_size,
+        response.decoded_size()
+    );
+    if let Some(certificate) = &response.certificate {
+        println!("{}", security_summary(certificate));
+    }
+    Ok(response)
+}
+
+/** Markup shown in place of a page that could not be loaded. */
+fn error_page(url: &str, error: &NetworkError) -> String {
+    let title = match error {
+        NetworkError::Timeout(_) => "The connection has timed out",
+        _ => "Problem loading page",
+    };
+    format!(
+        "<html><head><title>{0}</title></head><body>\
+         <h1>{0}</h1>\
+         <p>Broust could not load <b>{1}</b>.</p>\
+         <p><code>{2}</code></p></body></html>",
+        title,
+        html_escape::encode_text(url),
+        html_escape::encode_text(&error.to_string())
+    )
+}
+
+/** A fetched and parsed page, ready for layout. */
+pub struct Page {
+    pub r#type: String,
+    pub tree: Arc<Mutex<TreeNode>>,
+    /**
+     What links and subresources resolve against: the document's `<base href>`, or else the
+     URL it came from. `None` for pages the browser makes itself, such as error pages.
+    */
+    // Nothing follows links yet.
+    #[allow(dead_code)]
+    pub base_url: Option<URL>,
+}
+
+/** The URL that relative references in `tree`, fetched from `url`, resolve against. */
+fn base_url(url: &URL, tree: &Arc<Mutex<TreeNode>>) -> URL {
+    match HTMLParser::base_href(tree) {
+        Some(href) => url.resolve(&href).unwrap_or(url.clone()),
+        None => url.clone(),
+    }
+}
+
+/** An HTML response being parsed as it arrives, so it can be shown before it is complete. */
+struct Incremental {
+    url: URL,
+    status: u16,
+    text: TextDecoder,
+    parser: HTMLParser,
+    /** Decoded body bytes parsed so far. */
+    received: usize,
+}
+
+/** Where the response arriving for a page load is going. */
+enum Streamed {
+    Page(Box<Incremental>),
+    Download(Saving),
+}
+
+/** The `about:downloads` list, shown in place of a response that was saved instead. */
+fn downloads_page() -> Page {
+    let html = URL::new("about:downloads")
+        .and_then(|url| networking::about::fetch(&url))
+        .map(|response| response.text())
+        .unwrap_or_default();
+    Page {
+        r#type: "url".to_string(),
+        tree: HTMLParser::new(html).parse(),
+        base_url: None,
+    }
+}
+
+/**
+ Parse HTML responses to `requested` as they stream in, sending the partial tree to
+ `sender` after each piece, and write downloads straight to disk. Redirects are skipped,
+ since they are not what ends up on screen, and view-source never downloads.
+*/
+fn stream_into(
+    streamed: Rc<RefCell<Option<Streamed>>>,
+    requested: String,
+    r#type: String,
+    sender: Sender<Message>,
+) -> impl FnMut(Stream) {
+    move |piece| match piece {
+        Stream::Start(url, status, headers) => {
+            // A response that is retried ends whatever the last attempt started.
+            drop(streamed.borrow_mut().take());
+            let content_type = headers.get("content-type");
+            let next = if r#type != "source" && download::wanted(status, headers) {
+                progress::divert();
+                let saving = download::begin(&requested, url, status, headers);
+                let _ = sender.send(Message::Partial(downloads_page()));
+                Some(Streamed::Download(saving))
+            } else if !(300..400).contains(&status) && charset::is_html(content_type) {
+                Some(Streamed::Page(Box::new(Incremental {
+                    url: url.clone(),
+                    status,
+                    text: TextDecoder::new(content_type),
+                    parser: HTMLParser::new(String::new()),
+                    received: 0,
+                })))
+            } else {
+                None
+            };
+            *streamed.borrow_mut() = next;
+        }
+        Stream::Data(data) => match streamed.borrow_mut().as_mut() {
+            Some(Streamed::Download(saving)) => saving.write(data),
+            Some(Streamed::Page(incremental)) => {
+                incremental.received += data.len();
+                let text = incremental.text.decode(data);
+                incremental.parser.feed(&text);
+                if let Some(tree) = incremental.parser.root() {
+                    let r#type = r#type.clone();
+                    let base_url = Some(base_url(&incremental.url, &tree));
+                    let _ = sender.send(Message::Partial(Page {
+                        r#type,
+                        tree,
+                        base_url,
+                    }));
+                }
+            }
+            None => {}
+        },
+    }
+}
+
+/**
+ Fetch and parse `url`, turning failures into an error page. The tree streamed in for the
+ final response is finished and kept; anything else, such as a cached page, is parsed whole.
+ Downloads are finished and shown on the list of downloads instead.
+*/
+fn load_page(
+    url: &str,
+    method: Method,
+    body: Option<Body>,
+    streamed: &RefCell<Option<Streamed>>,
+) -> Page {
+    let loaded = URL::new(url).and_then(|parsed| Ok((load(&parsed, method, body)?, parsed)));
+    let streamed = match streamed.borrow_mut().take() {
+        Some(Streamed::Download(saving)) => {
+            saving.finish(loaded.as_ref().err());
+            return downloads_page();
+        }
+        Some(Streamed::Page(incremental)) => Some(incremental),
+        None => None,
+    };
+    if let Ok((response, parsed)) = &loaded {
+        // A download that did not stream in, such as a cached one, is saved all at once.
+        if parsed.r#type != "source" && download::wanted(response.status, &response.headers) {
+            download::save(&parsed.to_string(), response);
+            return downloads_page();
+        }
+    }
+    let (page_url, r#type, tree) = match loaded {
+        Ok((response, parsed)) => {
+            let _ = progress::phase(Phase::Parsing);
+            let tree = match streamed {
+                Some(mut streamed)
+                    if streamed.status == response.status
+                        && streamed.received == response.body.len() =>
+                {
+                    let rest = streamed.text.finish();
+                    streamed.parser.feed(&rest);
+                    streamed.parser.finish()
+                }
+                _ => HTMLParser::new(document(&response)).parse(),
+            };
+            (Some(response.url), parsed.r#type, tree)
+        }
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", url, e);
+            let tree = HTMLParser::new(error_page(url, &e)).parse();
+            (None, "url".to_string(), tree)
+        }
+    };
+    println!("{:?}", tree.lock().unwrap());
+    let base_url = page_url.map(|page_url| base_url(&page_url, &tree));
+    Page {
+        r#type,
+        tree,
+        base_url,
+    }
+}
+
+/** A request for credentials from the worker, which waits until it is answered. */
+pub struct Login {
+    pub prompt: Prompt,
+    reply: Sender<Option<Credentials>>,
+}
+
+impl Login {
+    /** Hand the user's credentials to the waiting load, or `None` to give up. */
+    pub fn answer(&self, credentials: Option<Credentials>) {
+        let _ = self.reply.send(credentials);
+    }
+}
+
+/** What a loader reports back to the render loop. */
+pub enum Message {
+    Progress(Progress),
+    Login(Login),
+    /** The page as far as it has arrived. Its tree keeps growing until `Done`. */
+    Partial(Page),
+    Done(Page),
+}
+
+/**
+ A page being fetched and parsed on a worker thread, so the window keeps drawing.
+ Dropping the loader cancels the load.
+*/
+pub struct Loader {
+    receiver: Receiver<Message>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Loader {
+    pub fn start(url: String, method: Method, body: Option<Body>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let watched = Arc::clone(&cancelled);
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            progress::watch(
+                move |progress| {
+                    let _ = progress_sender.send(Message::Progress(progress));
+                },
+                watched,
+            );
+            let login_sender = sender.clone();
+            auth::prompt_with(move |prompt| {
+                let (reply, answer) = mpsc::channel();
+                let login = Login {
+                    prompt: prompt.clone(),
+                    reply,
+                };
+                login_sender.send(Message::Login(login)).ok()?;
+                // If the load is cancelled, the unanswered login is dropped and this gives up.
+                answer.recv().ok().flatten()
+            });
+            let streamed = Rc::new(RefCell::new(None));
+            let (requested, r#type) = match URL::new(&url) {
+                Ok(parsed) => (parsed.to_string(), parsed.r#type),
+                Err(_) => (url.clone(), "url".to_string()),
+            };
+            progress::stream(stream_into(
+                Rc::clone(&streamed),
+                requested,
+                r#type,
+                sender.clone(),
+            ));
+            let page = load_page(&url, method, body, &streamed);
+            let _ = sender.send(Message::Done(page));
+        });
+        Self {
+            receiver,
+            cancelled,
+        }
+    }
+
+    /** Every message sent since the last call, without waiting for more. */
+    pub fn poll(&self) -> Vec<Message> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Load `url` the way the worker does, without streaming. */
+    fn load_whole(url: &str) -> Page {
+        load_page(url, Method::Get, None, &RefCell::new(None))
+    }
+
+    #[test]
+    fn base_href_is_resolved_against_the_page() {
+        let dir = std::env::temp_dir().join(format!("base-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("pages")).unwrap();
+        let with_base = dir.join("pages/with-base.html");
+        let without_base = dir.join("pages/without-base.html");
+        std::fs::write(
+            &with_base,
+            "<html><head><base href=\"../assets/\"></head><body>hi</body></html>",
+        )
+        .unwrap();
+        std::fs::write(&without_base, "<html><body>hi</body></html>").unwrap();
+        let url = |path: &std::path::Path| format!("file://{}", path.display());
+
+        let page = load_whole(&url(&with_base));
+        let expected = format!("file://{}/assets/", dir.display());
+        assert_eq!(page.base_url.map(|url| url.to_string()), Some(expected));
+        let page = load_whole(&url(&without_base));
+        assert_eq!(
+            page.base_url.map(|url| url.to_string()),
+            Some(url(&without_base))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        // An error page has nothing to resolve against.
+        assert!(load_whole(&url(&with_base)).base_url.is_none());
+    }
+}
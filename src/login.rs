@@ -0,0 +1,97 @@
+use crate::loader::Login;
+use crate::networking::auth::Credentials;
+use macroquad::prelude::*;
+
+const WIDTH: f32 = 440.0;
+const HEIGHT: f32 = 200.0;
+
+/** An in-window dialog asking for a user name and password for a protected page. */
+pub struct LoginDialog {
+    login: Login,
+    username: String,
+    password: String,
+    editing_password: bool,
+}
+
+impl LoginDialog {
+    pub fn new(login: Login) -> Self {
+        Self {
+            login,
+            username: String::new(),
+            password: String::new(),
+            editing_password: false,
+        }
+    }
+
+    /**
+     Apply this frame's typing. Enter submits, Esc cancels and Tab moves between the fields.
+     Returns `true` once the dialog has been answered and should close.
+    */
+    pub fn handle_input(&mut self) -> bool {
+        if is_key_pressed(KeyCode::Escape) {
+            self.login.answer(None);
+            return true;
+        }
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+            self.login.answer(Some(Credentials {
+                username: self.username.clone(),
+                password: self.password.clone(),
+            }));
+            return true;
+        }
+        if is_key_pressed(KeyCode::Tab) {
+            self.editing_password = !self.editing_password;
+        }
+        let field = match self.editing_password {
+            true => &mut self.password,
+            false => &mut self.username,
+        };
+        if is_key_pressed(KeyCode::Backspace) {
+            field.pop();
+        }
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() {
+                field.push(c);
+            }
+        }
+        false
+    }
+
+    pub fn draw(&self) {
+        draw_rectangle(
+            0.0,
+            0.0,
+            screen_width(),
+            screen_height(),
+            Color::new(0.0, 0.0, 0.0, 0.3),
+        );
+        let x = ((screen_width() - WIDTH) / 2.0).max(0.0);
+        let y = ((screen_height() - HEIGHT) / 2.0).max(0.0);
+        draw_rectangle(x, y, WIDTH, HEIGHT, WHITE);
+        draw_rectangle_lines(x, y, WIDTH, HEIGHT, 2.0, DARKGRAY);
+
+        let prompt = &self.login.prompt;
+        draw_text("Authentication required", x + 16.0, y + 32.0, 26.0, BLACK);
+        let asks = format!("{} asks for \"{}\"", prompt.origin, prompt.realm);
+        draw_text(&asks, x + 16.0, y + 60.0, 18.0, DARKGRAY);
+        if prompt.retry {
+            let rejected = "Those credentials were not accepted.";
+            draw_text(rejected, x + 16.0, y + 82.0, 18.0, RED);
+        }
+        let caret = |editing: bool| if editing { "_" } else { "" };
+        let username = format!(
+            "User name: {}{}",
+            self.username,
+            caret(!self.editing_password)
+        );
+        let password = format!(
+            "Password: {}{}",
+            "*".repeat(self.password.chars().count()),
+            caret(self.editing_password)
+        );
+        draw_text(&username, x + 16.0, y + 116.0, 22.0, BLACK);
+        draw_text(&password, x + 16.0, y + 146.0, 22.0, BLACK);
+        let hint = "Tab: next field   Enter: log in   Esc: cancel";
+        draw_text(hint, x + 16.0, y + 182.0, 16.0, GRAY);
+    }
+}
@@ -3,10 +3,24 @@ use std::collections::HashMap;
 
 mod dom;
 mod layout;
+mod loader;
+mod login;
 mod networking;
-use dom::HTMLParser;
+use loader::{Loader, Message, Page};
+use login::LoginDialog;
 use macroquad::prelude::*;
+use networking::progress::{Phase, Progress};
+use networking::request::{Body, Method};
 const SCROLL_DISTANCE: f32 = 100.0;
+/** Command line flags that take no value. */
+const SWITCHES: [&str; 6] = [
+    "persist-cookies",
+    "persist-hsts",
+    "no-hsts-preload",
+    "persist-cache",
+    "http1.0",
+    "insecure",
+];
 
 fn window_conf() -> Conf {
     Conf {
@@ -15,30 +29,215 @@ fn window_conf() -> Conf {
     }
 }
 
+/** Split the command line into `--flag value` options, `--switch`es and the URL to open. */
+fn parse_args(args: &[String]) -> (HashMap<String, String>, Option<String>) {
+    let mut options = HashMap::new();
+    let mut url = None;
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if let Some(flag) = arg.strip_prefix("--") {
+            let value = if SWITCHES.contains(&flag) {
+                String::new()
+            } else {
+                args.next().cloned().unwrap_or_default()
+            };
+            options.insert(flag.to_owned(), value);
+        } else {
+            url = Some(arg.to_owned());
+        }
+    }
+    (options, url)
+}
+
+/** Report a command line option that cannot be used and exit. */
+fn usage_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(2);
+}
+
+/** Draw a bar along the top of the window and a line of text saying how the load is going. */
+fn draw_progress(progress: &Progress) {
+    let kb = |bytes: usize| format!("{:.1} KB", bytes as f32 / 1024.0);
+    let (label, fraction) = match (progress.phase, progress.content_length) {
+        (Phase::Connecting, _) => ("Connecting".to_owned(), 0.05),
+        (Phase::Waiting, _) => ("Waiting for response".to_owned(), 0.1),
+        (Phase::Receiving, Some(length)) if length > 0 => (
+            format!("Received {} of {}", kb(progress.received), kb(length)),
+            progress.received as f32 / length as f32,
+        ),
+        (Phase::Receiving, _) => (format!("Received {}", kb(progress.received)), 0.5),
+        (Phase::Parsing, _) => ("Parsing".to_owned(), 1.0),
+    };
+    draw_rectangle(0.0, 0.0, screen_width() * fraction.min(1.0), 3.0, BLUE);
+    draw_text(
+        &format!("{}... (Esc to stop)", label),
+        8.0,
+        screen_height() - 8.0,
+        18.0,
+        DARKGRAY,
+    );
+}
+
 #[macroquad::main(window_conf)]
 async fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 1 {
-        panic!("Not enough arguments! add a `-- {{url}}` at the end of the CLI");
-    }
-    let text = networking::url::URL::new(&args[1]);
-    let r#type = &text.r#type;
-    let text = &text.request();
-    let tree = HTMLParser::new(text.to_string()).parse();
-    println!("{:?}", tree);
+    let (options, url) = parse_args(&args);
+    let url = url.unwrap_or_else(|| "about:blank".to_string());
+    let mut config = networking::config::Config::default();
+    if let Some(max) = options.get("max-redirects") {
+        config.max_redirects = max
+            .parse()
+            .unwrap_or_else(|_| usage_error("--max-redirects takes a number"));
+    }
+    if let Some(path) = options.get("credentials-file") {
+        config.credentials_file = Some(path.into());
+    }
+    if let Some(path) = options.get("cookie-file") {
+        config.cookie_file = Some(path.into());
+    } else if options.contains_key("persist-cookies") {
+        config.cookie_file = networking::config::config_dir().map(|dir| dir.join("cookies.txt"));
+    }
+    if let Some(path) = options.get("hsts-file") {
+        config.hsts_file = Some(path.into());
+    } else if options.contains_key("persist-hsts") {
+        config.hsts_file = networking::config::config_dir().map(|dir| dir.join("hsts.txt"));
+    }
+    if options.contains_key("no-hsts-preload") {
+        config.hsts_preload = false;
+    }
+    if let Some(path) = options.get("downloads-dir") {
+        config.downloads_dir = Some(path.into());
+    }
+    if let Some(path) = options.get("cache-dir") {
+        config.cache_dir = Some(path.into());
+    } else if options.contains_key("persist-cache") {
+        config.cache_dir = networking::config::default_cache_dir();
+    }
+    if let Some(size) = options.get("cache-size") {
+        config.cache_size = size
+            .parse()
+            .unwrap_or_else(|_| usage_error("--cache-size takes a number of bytes"));
+    }
+    if let Some(size) = options.get("max-decoded-size") {
+        config.max_decoded_size = size
+            .parse()
+            .unwrap_or_else(|_| usage_error("--max-decoded-size takes a number of bytes"));
+    }
+    // Timeouts are given in seconds, where 0 means no limit.
+    let seconds = |flag: &str| {
+        options.get(flag).map(|value| {
+            let seconds: u64 = value
+                .parse()
+                .unwrap_or_else(|_| usage_error(&format!("--{} takes a number of seconds", flag)));
+            (seconds > 0).then(|| std::time::Duration::from_secs(seconds))
+        })
+    };
+    if let Some(timeout) = seconds("connect-timeout") {
+        config.connect_timeout = timeout;
+    }
+    if let Some(timeout) = seconds("read-timeout") {
+        config.read_timeout = timeout;
+    }
+    if let Some(timeout) = seconds("timeout") {
+        config.total_timeout = timeout;
+    }
+    // --proxy applies to both schemes; NO_PROXY from the environment still counts.
+    config.proxy = networking::proxy::ProxySettings::from_env();
+    if let Some(proxy) = options.get("proxy") {
+        let proxy = networking::proxy::Proxy::parse(proxy)
+            .unwrap_or_else(|_| usage_error("--proxy takes a proxy URL"));
+        config.proxy.http = Some(proxy.clone());
+        config.proxy.https = Some(proxy);
+    }
+    if let Some(paths) = options.get("ca-cert") {
+        config.tls.extra_roots = env::split_paths(paths).collect();
+    }
+    if let Some(path) = options.get("client-cert") {
+        config.tls.identity = Some(path.into());
+        config.tls.identity_password = options
+            .get("client-cert-password")
+            .cloned()
+            .unwrap_or_default();
+    }
+    if options.contains_key("insecure") {
+        eprintln!("WARNING: --insecure turns off TLS certificate and host name checks.");
+        config.tls.insecure = true;
+    }
+    if options.contains_key("http1.0") {
+        config.http_version = networking::request::Version::Http10;
+    }
+    if let Some(path) = options.get("record") {
+        config.archive = Some(networking::archive::Mode::Record(path.into()));
+    } else if let Some(path) = options.get("replay") {
+        config.archive = Some(networking::archive::Mode::Replay(path.into()));
+    }
+    networking::config::set(config);
+    // --data sends a form submission, which is a POST unless --method says otherwise.
+    let body = options.get("data").map(|data| Body {
+        content_type: "application/x-www-form-urlencoded".to_owned(),
+        data: data.as_bytes().to_vec(),
+    });
+    let method = match options.get("method") {
+        Some(name) => Method::parse(name)
+            .unwrap_or_else(|| usage_error("--method takes GET, HEAD, POST, PUT or DELETE")),
+        None if body.is_some() => Method::Post,
+        None => Method::Get,
+    };
+    let mut loader = Some(Loader::start(url, method, body));
+    let mut progress: Option<Progress> = None;
+    let mut page: Option<Page> = None;
     let font = layout::DefaultFont::default();
     let mut curr_w = screen_width();
     let mut cache: HashMap<String, TextDimensions> = HashMap::new();
-    let mut layout_obj = layout::Layout::new(r#type.to_string());
-    layout_obj.layout(&mut cache, &tree, &font);
+    let mut layout_obj = layout::Layout::new("url".to_string());
     let mut scroll = 0.0;
+    let mut relayout = false;
+    let mut login: Option<LoginDialog> = None;
 
     loop {
         clear_background(WHITE);
         let (_mouse_wheel_x, mouse_wheel_y) = mouse_wheel();
 
+        for message in loader.as_ref().map(Loader::poll).unwrap_or_default() {
+            match message {
+                Message::Progress(update) => progress = Some(update),
+                Message::Login(request) => login = Some(LoginDialog::new(request)),
+                Message::Partial(partial) => {
+                    page = Some(partial);
+                    relayout = true;
+                }
+                Message::Done(done) => {
+                    page = Some(done);
+                    relayout = true;
+                    loader = None;
+                }
+            }
+        }
+        // However many pieces arrived this frame, lay the page out once.
+        if relayout {
+            if let Some(page) = &page {
+                layout_obj = layout::Layout::new(page.r#type.clone());
+                layout_obj.layout(&mut cache, &page.tree, &font);
+            }
+            relayout = false;
+        }
+        // While the login dialog is up it takes the keyboard, Esc included.
+        if let Some(dialog) = &mut login {
+            if dialog.handle_input() {
+                login = None;
+            }
+        } else if loader.is_some() && is_key_pressed(KeyCode::Escape) {
+            // Dropping the loader cancels it; whatever was shown before stays up.
+            loader = None;
+        }
+        if loader.is_none() {
+            progress = None;
+        }
+
         if curr_w != screen_width() {
-            layout_obj.layout(&mut cache, &tree, &font);
+            if let Some(page) = &page {
+                layout_obj.layout(&mut cache, &page.tree, &font);
+            }
             curr_w = screen_width();
         }
 
@@ -53,17 +252,23 @@ async fn main() {
             }
 
             draw_text_ex(
-                &c,
+                c,
                 *x,
                 y - scroll,
                 TextParams {
-                    font: Some(&style),
+                    font: Some(style),
                     font_size: *font_size,
                     color: *color,
                     ..Default::default()
                 },
             );
         }
+        if let Some(progress) = &progress {
+            draw_progress(progress);
+        }
+        if let Some(dialog) = &login {
+            dialog.draw();
+        }
         next_frame().await
     }
 }
@@ -0,0 +1,178 @@
+use crate::networking::compression;
+use crate::networking::download::{self, State};
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::response::Response;
+use crate::networking::url::URL;
+use std::sync::Mutex;
+
+/** URLs visited this session, oldest first. */
+static HISTORY: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/** Remember a top-level navigation for `about:history`. Built-in pages are not recorded. */
+pub fn record_visit(url: &URL) {
+    if url.scheme != "about" {
+        HISTORY.lock().unwrap().push(url.to_string());
+    }
+}
+
+/** Generate the built-in page named by an `about:` URL. */
+pub fn fetch(url: &URL) -> Result<Response> {
+    let body = match url.path.as_str() {
+        "blank" => "<html><head></head><body></body></html>".to_string(),
+        "version" => version(),
+        "history" => history(),
+        "downloads" => downloads(),
+        page => {
+            return Err(NetworkError::InvalidUrl(format!(
+                "unknown page about:{}",
+                page
+            )))
+        }
+    };
+    Ok(Response::local(url, "text/html", body.into_bytes()))
+}
+
+fn version() -> String {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    format!(
+        "<html><head><title>About Broust</title></head><body>\
+         <h1>Broust {}</h1>\
+         <p>Build: <code>{}</code></p>\
+         <p>URL schemes: <code>http https file data about view-source</code></p>\
+         <p>Content encodings: <code>{}</code></p>\
+         </body></html>",
+        env!("CARGO_PKG_VERSION"),
+        profile,
+        compression::ACCEPT_ENCODING
+    )
+}
+
+fn history() -> String {
+    let mut html = "<html><head><title>History</title></head><body><h1>History</h1>".to_string();
+    let history = HISTORY.lock().unwrap();
+    if history.is_empty() {
+        html += "<p>No pages visited yet.</p>";
+    }
+    for url in history.iter().rev() {
+        html += &format!(
+            "<p><a href=\"{}\">{}</a></p>",
+            html_escape::encode_double_quoted_attribute(url),
+            html_escape::encode_text(url)
+        );
+    }
+    html += "</body></html>";
+    html
+}
+
+/** A byte count in the largest unit that keeps it above one. */
+fn size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} bytes", bytes),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+fn downloads() -> String {
+    let mut html =
+        "<html><head><title>Downloads</title></head><body><h1>Downloads</h1>".to_string();
+    let downloads = download::list();
+    if downloads.is_empty() {
+        html += "<p>Nothing downloaded yet.</p>";
+    }
+    for download in downloads.iter().rev() {
+        let name = download
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let received = match download.total {
+            Some(total) => format!("{} of {}", size(download.received), size(total)),
+            None => size(download.received),
+        };
+        let state = match &download.state {
+            State::Downloading => format!("Downloading, {}", received),
+            State::Complete => format!("Done, {}", size(download.received)),
+            State::Cancelled => format!("Cancelled after {}", received),
+            State::Failed(reason) => format!("Failed after {}: {}", received, reason),
+        };
+        let next = match &download.state {
+            State::Downloading | State::Complete => "",
+            _ if download.resumable() => " Open the URL again to resume.",
+            _ => " Open the URL again to start over.",
+        };
+        html += &format!(
+            "<p><b>{}</b></p><p>{}.{}</p><p>From <a href=\"{}\">{}</a></p><p>Saved to <code>{}</code></p>",
+            html_escape::encode_text(&name),
+            html_escape::encode_text(&state),
+            next,
+            html_escape::encode_double_quoted_attribute(&download.url),
+            html_escape::encode_text(&download.url),
+            html_escape::encode_text(&download.path.display().to_string())
+        );
+    }
+    html += "</body></html>";
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(url: &str) -> Result<String> {
+        let response = fetch(&URL::new(url).unwrap())?;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers.get("content-type"), Some("text/html"));
+        Ok(String::from_utf8(response.body).unwrap())
+    }
+
+    #[test]
+    fn blank_is_an_empty_document() {
+        assert_eq!(
+            page("about:blank").unwrap(),
+            "<html><head></head><body></body></html>"
+        );
+    }
+
+    #[test]
+    fn unknown_pages_are_errors() {
+        for url in ["about:nothing", "about:", "about:Blank"] {
+            assert!(matches!(
+                page(url),
+                Err(NetworkError::InvalidUrl(message)) if message.contains("unknown page")
+            ));
+        }
+    }
+
+    #[test]
+    fn version_names_the_build() {
+        let html = page("about:version").unwrap();
+        assert!(html.contains(env!("CARGO_PKG_VERSION")));
+        assert!(html.contains(compression::ACCEPT_ENCODING));
+    }
+
+    #[test]
+    fn history_lists_visits_newest_first_and_escaped() {
+        record_visit(&URL::new("http://history.test/first").unwrap());
+        record_visit(&URL::new("http://history.test/second?a=<b>").unwrap());
+        record_visit(&URL::new("about:blank").unwrap());
+        let html = page("about:history").unwrap();
+        let first = html.find("http://history.test/first").unwrap();
+        let second = html.find("http://history.test/second").unwrap();
+        assert!(second < first);
+        assert!(!html.contains("<b>"));
+        assert!(!html.contains(">about:blank<"));
+    }
+
+    #[test]
+    fn sizes_use_the_largest_unit() {
+        assert_eq!(size(0), "0 bytes");
+        assert_eq!(size(1023), "1023 bytes");
+        assert_eq!(size(1536), "1.5 KB");
+        assert_eq!(size(3 * 1_048_576), "3.0 MB");
+    }
+}
@@ -0,0 +1,362 @@
+use crate::networking::config;
+use crate::networking::date;
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::request::Request;
+use crate::networking::response::{read_line, HeaderMap};
+use crate::networking::transport::MemoryTransport;
+use crate::networking::url::URL;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/** Whether exchanges are written to an archive or answered from one instead of the network. */
+#[derive(Debug, Clone)]
+pub enum Mode {
+    /** Append every request and the response as received to this WARC file. */
+    Record(PathBuf),
+    /** Answer requests with the responses recorded in this WARC file. */
+    Replay(PathBuf),
+}
+
+/** Responses recorded for one method and URL, served in order, then the last one again. */
+struct Recorded {
+    responses: Vec<Vec<u8>>,
+    next: usize,
+}
+
+enum Archive {
+    Recording(File),
+    Replaying(HashMap<String, Recorded>),
+}
+
+impl Recorded {
+    /** The response to serve next. */
+    fn next(&mut self) -> &[u8] {
+        let response = &self.responses[self.next];
+        self.next = (self.next + 1).min(self.responses.len() - 1);
+        response
+    }
+}
+
+/** How recorded exchanges are looked up: the method and the URL without its fragment. */
+fn key(method: &str, url: &str) -> String {
+    format!("{} {}", method, url)
+}
+
+/** A fresh `<urn:uuid:…>` WARC record ID, from a version 4 UUID. */
+fn record_id() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    let (high, low) = (random(), random());
+    format!(
+        "<urn:uuid:{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}>",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xfff,
+        0x8000 | ((low >> 48) & 0x3fff),
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/** Write one WARC record: the version line, its fields, the block and two CRLFs. */
+fn write_record(file: &mut File, fields: &[(&str, &str)], block: &[u8]) -> io::Result<()> {
+    let mut head = "WARC/1.1\r\n".to_owned();
+    for (name, value) in fields {
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    head += &format!("Content-Length: {}\r\n\r\n", block.len());
+    let mut record = head.into_bytes();
+    record.extend_from_slice(block);
+    record.extend_from_slice(b"\r\n\r\n");
+    // One write per record, so a crash cannot leave half a record behind another.
+    file.write_all(&record)
+}
+
+/** Open `path` for appending, starting it with a `warcinfo` record if it is new. */
+fn create(path: &Path) -> io::Result<File> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        let info = format!("software: Broust/{}\r\n", env!("CARGO_PKG_VERSION"));
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        write_record(
+            &mut file,
+            &[
+                ("WARC-Type", "warcinfo"),
+                ("WARC-Record-ID", &record_id()),
+                ("WARC-Date", &date::format_iso(SystemTime::now())),
+                ("WARC-Filename", &filename),
+                ("Content-Type", "application/warc-fields"),
+            ],
+            info.as_bytes(),
+        )?;
+    }
+    Ok(file)
+}
+
+/**
+ Append a request record for `request` to `target` and a response record with the
+ `response` bytes received for it. Credentials and cookies are left out of the request,
+ since archives are made to be shared.
+*/
+fn append(file: &mut File, target: &str, request: &Request, response: &[u8]) -> io::Result<()> {
+    let now = date::format_iso(SystemTime::now());
+    let request_id = record_id();
+    write_record(
+        file,
+        &[
+            ("WARC-Type", "request"),
+            ("WARC-Record-ID", &request_id),
+            ("WARC-Date", &now),
+            ("WARC-Target-URI", target),
+            ("Content-Type", "application/http;msgtype=request"),
+        ],
+        &request.to_redacted_bytes(),
+    )?;
+    write_record(
+        file,
+        &[
+            ("WARC-Type", "response"),
+            ("WARC-Record-ID", &record_id()),
+            ("WARC-Date", &now),
+            ("WARC-Target-URI", target),
+            ("WARC-Concurrent-To", &request_id),
+            ("Content-Type", "application/http;msgtype=response"),
+        ],
+        response,
+    )
+}
+
+/**
+ Read every request and response record in a WARC file. Responses are paired with the
+ method of their request through `WARC-Concurrent-To`, defaulting to GET.
+*/
+fn load(path: &Path) -> Result<HashMap<String, Recorded>> {
+    let invalid = |reason: &str| NetworkError::Archive(format!("{}: {}", path.display(), reason));
+    let file = File::open(path).map_err(|e| invalid(&e.to_string()))?;
+    let mut reader = BufReader::new(file);
+    let mut methods = HashMap::new();
+    let mut exchanges: HashMap<String, Recorded> = HashMap::new();
+    loop {
+        if reader.fill_buf()?.is_empty() {
+            break;
+        }
+        let version = read_line(&mut reader)?;
+        if version.is_empty() {
+            // The blank lines that end the previous record.
+            continue;
+        }
+        if !version.starts_with("WARC/") {
+            return Err(invalid(&format!(
+                "expected a WARC record, found {:?}",
+                version
+            )));
+        }
+        let mut fields = HeaderMap::new();
+        loop {
+            let line = read_line(&mut reader)?;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| invalid(&format!("malformed field {:?}", line)))?;
+            fields.insert(name, value);
+        }
+        let length = fields
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| invalid("record without a Content-Length"))?;
+        let mut block = vec![0; length];
+        reader.read_exact(&mut block)?;
+
+        let id = fields.get("warc-record-id").unwrap_or_default().to_owned();
+        let uri = fields.get("warc-target-uri").unwrap_or_default().to_owned();
+        match fields.get("warc-type") {
+            Some("request") => {
+                let method = block.split(|&b| b == b' ').next().unwrap_or_default();
+                methods.insert(id, String::from_utf8_lossy(method).into_owned());
+            }
+            Some("response") => {
+                let method = fields
+                    .get("warc-concurrent-to")
+                    .and_then(|request| methods.get(request))
+                    .map_or("GET", |method| method.as_str());
+                exchanges
+                    .entry(key(method, &uri))
+                    .or_insert(Recorded {
+                        responses: Vec::new(),
+                        next: 0,
+                    })
+                    .responses
+                    .push(block);
+            }
+            // warcinfo, metadata and the rest say nothing about what to serve.
+            _ => {}
+        }
+    }
+    Ok(exchanges)
+}
+
+static ARCHIVE: Mutex<Option<Archive>> = Mutex::new(None);
+
+/** Run `f` on the archive `mode` names, opening or loading it on first use. */
+fn with_archive<T>(mode: &Mode, f: impl FnOnce(&mut Archive) -> Result<T>) -> Result<T> {
+    let mut archive = ARCHIVE.lock().unwrap();
+    let archive = match archive.as_mut() {
+        Some(archive) => archive,
+        None => archive.insert(match mode {
+            Mode::Record(path) => Archive::Recording(
+                create(path)
+                    .map_err(|e| NetworkError::Archive(format!("{}: {}", path.display(), e)))?,
+            ),
+            Mode::Replay(path) => Archive::Replaying(load(path)?),
+        }),
+    };
+    f(archive)
+}
+
+/** Whether exchanges should be recorded, so callers know to keep the bytes received. */
+pub fn recording() -> bool {
+    matches!(config::get().archive, Some(Mode::Record(_)))
+}
+
+/** Append `request` to `url` and the `response` bytes received for it to the archive. */
+pub fn record(url: &URL, request: &Request, response: &[u8]) {
+    let Some(mode) = config::get().archive else {
+        return;
+    };
+    let Mode::Record(path) = &mode else {
+        return;
+    };
+    let target = url.absolute_form();
+    let recorded = with_archive(&mode, |archive| {
+        let Archive::Recording(file) = archive else {
+            return Ok(());
+        };
+        Ok(append(file, &target, request, response)?)
+    });
+    if let Err(e) = recorded {
+        eprintln!("Failed to record {} to {}: {}", target, path.display(), e);
+    }
+}
+
+/**
+ A transport that plays back the response recorded for `request` to `url`, or an error if
+ the archive has none. Bodies are not compared, so a POST matches any recorded POST.
+*/
+pub fn replay(url: &URL, request: &Request) -> Result<MemoryTransport> {
+    let not_replaying = || NetworkError::Archive("not replaying an archive".to_owned());
+    let mode = config::get().archive.ok_or_else(not_replaying)?;
+    let Mode::Replay(path) = &mode else {
+        return Err(not_replaying());
+    };
+    let key = key(request.method.as_str(), &url.absolute_form());
+    with_archive(&mode, |archive| {
+        let Archive::Replaying(exchanges) = archive else {
+            return Err(not_replaying());
+        };
+        let recorded = exchanges.get_mut(&key).ok_or_else(|| {
+            NetworkError::Archive(format!("{} is not in {}", key, path.display()))
+        })?;
+        Ok(MemoryTransport::new(recorded.next()))
+    })
+}
+
+/** Passes a response through while keeping a copy of every byte consumed, for `record`. */
+pub struct Tap<R> {
+    inner: R,
+    pub bytes: Vec<u8>,
+}
+
+impl<R> Tap<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            bytes: Vec::new(),
+        }
+    }
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: BufRead> Read for Tap<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Tap<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.bytes.extend_from_slice(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::request::{Body, Method};
+    use crate::networking::response::Response;
+    use crate::networking::transport;
+
+    #[test]
+    fn recorded_exchanges_replay_without_secrets() {
+        let path = std::env::temp_dir().join(format!("archive-{}.warc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let url = URL::new("http://example.com/form").unwrap();
+        let target = url.absolute_form();
+        let post = Request::new(Method::Post, "/form", "example.com")
+            .header("Authorization", "Basic dTpzZWNyZXQ=")
+            .header("Cookie", "session=hunter2")
+            .body(&Body {
+                content_type: "application/x-www-form-urlencoded".to_owned(),
+                data: b"q=1".to_vec(),
+            });
+        let get = Request::new(Method::Get, "/form", "example.com");
+
+        // Record what reading the response takes off the wire, as `transport::exchange` does.
+        let sent = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\
+                     Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        let mut tap = Tap::new(&sent[..]);
+        let (live, _) = Response::read(&url, Method::Post, &mut tap).unwrap();
+        let mut file = create(&path).unwrap();
+        append(&mut file, &target, &post, &tap.bytes).unwrap();
+        let missing = b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+        append(&mut file, &target, &get, missing).unwrap();
+        drop(file);
+
+        let text = String::from_utf8(std::fs::read(&path).unwrap()).unwrap();
+        assert!(!text.contains("dTpzZWNyZXQ=") && !text.contains("hunter2"));
+        assert!(text.contains("Authorization: [redacted]\r\n"));
+        assert!(text.contains("q=1"));
+
+        let mut exchanges = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let replay = |exchanges: &mut HashMap<String, Recorded>, request: &Request| {
+            let recorded = exchanges
+                .get_mut(&key(request.method.as_str(), &target))
+                .unwrap();
+            let mut stream = BufReader::new(MemoryTransport::new(recorded.next()));
+            transport::exchange(&mut stream, &url, request).unwrap().0
+        };
+        let replayed = replay(&mut exchanges, &post);
+        assert_eq!(replayed.status, live.status);
+        assert_eq!(replayed.body, b"hello");
+        assert_eq!(replayed.headers.get("content-type"), Some("text/plain"));
+        // Each method gets its own response, and replaying again gives the same one.
+        assert_eq!(replay(&mut exchanges, &get).status, 404);
+        assert_eq!(replay(&mut exchanges, &post).body, b"hello");
+    }
+}
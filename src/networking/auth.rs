@@ -0,0 +1,526 @@
+use crate::networking::config;
+use crate::networking::request::Request;
+use crate::networking::response::Response;
+use crate::networking::url::{percent_decode, URL};
+use base64::prelude::*;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::Mutex;
+
+/** How many times the user is asked for credentials before the `401` page is shown. */
+const MAX_PROMPTS: usize = 3;
+
+/** A user name and password for one protection space. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/** What the user is told when asked for credentials. */
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    /** The site asking, such as `https://docs.example.com`. */
+    pub origin: String,
+    /** The server's name for the protected area. */
+    pub realm: String,
+    /** Whether credentials were already rejected for this request. */
+    pub retry: bool,
+}
+
+/** One challenge from a `WWW-Authenticate` header: a scheme and its parameters. */
+#[derive(Debug)]
+struct Challenge {
+    scheme: String,
+    params: HashMap<String, String>,
+}
+
+impl Challenge {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|value| value.as_str())
+    }
+
+    /**
+     How much to prefer this challenge over others in the same response, or `None` if it
+     cannot be answered: Digest with SHA-256, then Digest with MD5, then Basic.
+    */
+    fn rank(&self) -> Option<u8> {
+        match self.scheme.as_str() {
+            "basic" => Some(1),
+            "digest" => {
+                // Without `auth` on offer only `auth-int` is left, which needs the body hashed.
+                let qop_ok = self
+                    .param("qop")
+                    .is_none_or(|qop| qop.split(',').any(|qop| qop.trim() == "auth"));
+                let algorithm = self.param("algorithm").unwrap_or("MD5").to_uppercase();
+                match algorithm.trim_end_matches("-SESS") {
+                    "SHA-256" if qop_ok => Some(3),
+                    "MD5" if qop_ok => Some(2),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/** A quoted-string or token value at the start of `input`, and what follows it. */
+fn parse_value(input: &str) -> (String, &str) {
+    let Some(quoted) = input.strip_prefix('"') else {
+        let end = input.find([',', ' ', '\t']).unwrap_or(input.len());
+        return (input[..end].to_owned(), &input[end..]);
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (value, &quoted[i + 1..]),
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            c => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+/**
+ Split a `WWW-Authenticate` value into challenges. Several can share one header, so a
+ token that is not followed by `=` starts a new challenge.
+*/
+fn parse_challenges(header: &str) -> Vec<Challenge> {
+    let mut challenges: Vec<Challenge> = Vec::new();
+    let mut rest = header;
+    loop {
+        rest = rest.trim_start_matches([',', ' ', '\t']);
+        if rest.is_empty() {
+            return challenges;
+        }
+        let end = rest.find([',', ' ', '\t', '=']).unwrap_or(rest.len());
+        let token = &rest[..end];
+        let after = rest[end..].trim_start_matches([' ', '\t']);
+        match (after.strip_prefix('='), challenges.last_mut()) {
+            (Some(value), Some(challenge)) if !token.is_empty() => {
+                let (value, remaining) = parse_value(value.trim_start_matches([' ', '\t']));
+                challenge.params.insert(token.to_lowercase(), value);
+                rest = remaining;
+            }
+            _ if token.is_empty() => rest = &after[1..],
+            _ => {
+                challenges.push(Challenge {
+                    scheme: token.to_lowercase(),
+                    params: HashMap::new(),
+                });
+                rest = after;
+            }
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/** Escape `value` for a quoted-string. */
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/** How to answer for a protection space. */
+#[derive(Debug, Clone)]
+enum Scheme {
+    Basic,
+    Digest {
+        nonce: String,
+        opaque: Option<String>,
+        /** As the server named it, such as `MD5-sess`. */
+        algorithm: String,
+        /** `auth` if the server offered it, or `None` for an RFC 2069 server. */
+        qop: Option<String>,
+        /** How many requests have answered this nonce. */
+        count: u32,
+    },
+}
+
+/** Credentials that a server accepted, and where they can be sent without being asked. */
+#[derive(Debug, Clone)]
+struct Space {
+    /** Scheme, host and port, such as `https://docs.example.com`. */
+    origin: String,
+    realm: String,
+    /** Paths under this one are taken to be in the same space, as RFC 7617 suggests. */
+    directory: String,
+    credentials: Credentials,
+    scheme: Scheme,
+}
+
+impl Space {
+    fn new(url: &URL, challenge: &Challenge, credentials: Credentials) -> Self {
+        let scheme = match challenge.scheme.as_str() {
+            "digest" => Scheme::Digest {
+                nonce: challenge.param("nonce").unwrap_or_default().to_owned(),
+                opaque: challenge.param("opaque").map(str::to_owned),
+                algorithm: challenge.param("algorithm").unwrap_or("MD5").to_owned(),
+                qop: challenge.param("qop").map(|_| "auth".to_owned()),
+                count: 0,
+            },
+            _ => Scheme::Basic,
+        };
+        Self {
+            origin: origin(url),
+            realm: challenge.param("realm").unwrap_or_default().to_owned(),
+            directory: directory(&url.path).to_owned(),
+            credentials,
+            scheme,
+        }
+    }
+
+    /** The `Authorization` value for a `method` request for `uri`, the request target. */
+    fn authorization(&mut self, method: &str, uri: &str) -> String {
+        let random = || RandomState::new().build_hasher().finish();
+        let cnonce = format!("{:016x}{:016x}", random(), random());
+        self.answer(method, uri, &cnonce)
+    }
+
+    /** `authorization` with the client nonce given, so that a known answer can be checked. */
+    fn answer(&mut self, method: &str, uri: &str, cnonce: &str) -> String {
+        let Credentials { username, password } = &self.credentials;
+        let Scheme::Digest {
+            nonce,
+            opaque,
+            algorithm,
+            qop,
+            count,
+        } = &mut self.scheme
+        else {
+            let credentials = format!("{}:{}", username, password);
+            return format!("Basic {}", BASE64_STANDARD.encode(credentials));
+        };
+        let upper = algorithm.to_uppercase();
+        let hash = |data: String| match upper.trim_end_matches("-SESS") {
+            "SHA-256" => hex(&Sha256::digest(data.as_bytes())),
+            _ => hex(&Md5::digest(data.as_bytes())),
+        };
+        *count += 1;
+        let nc = format!("{:08x}", count);
+        let mut ha1 = hash(format!("{}:{}:{}", username, self.realm, password));
+        if upper.ends_with("-SESS") {
+            ha1 = hash(format!("{}:{}:{}", ha1, nonce, cnonce));
+        }
+        let ha2 = hash(format!("{}:{}", method, uri));
+        let response = match qop {
+            Some(qop) => hash(format!(
+                "{}:{}:{}:{}:{}:{}",
+                ha1, nonce, nc, cnonce, qop, ha2
+            )),
+            None => hash(format!("{}:{}:{}", ha1, nonce, ha2)),
+        };
+        let mut value = format!(
+            "Digest username={}, realm={}, nonce={}, uri={}, algorithm={}, response={}",
+            quote(username),
+            quote(&self.realm),
+            quote(nonce),
+            quote(uri),
+            algorithm,
+            quote(&response)
+        );
+        if let Some(opaque) = opaque {
+            value += &format!(", opaque={}", quote(opaque));
+        }
+        if let Some(qop) = qop {
+            value += &format!(", qop={}, nc={}, cnonce={}", qop, nc, quote(cnonce));
+        }
+        value
+    }
+}
+
+/** The part of a URL that credentials are tied to. */
+fn origin(url: &URL) -> String {
+    format!("{}://{}", url.scheme, url.authority())
+}
+
+/** `path` up to and including its last slash. */
+fn directory(path: &str) -> &str {
+    &path[..path.rfind('/').map_or(0, |i| i + 1)]
+}
+
+/** The longest path both directories start with, ending at a slash. */
+fn common_directory(a: &str, b: &str) -> String {
+    let shared = a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count();
+    directory(&a[..shared]).to_owned()
+}
+
+/** The `login` and `password` for `host` in a `.netrc`-style file, or its `default` entry. */
+fn from_file(path: &Path, host: &str) -> Option<Credentials> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut tokens = text.split_whitespace();
+    let mut matching = false;
+    let mut found: Option<Credentials> = None;
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" | "default" if found.is_some() => break,
+            "machine" => matching = tokens.next() == Some(host),
+            "default" => matching = true,
+            "login" | "password" if matching => {
+                let value = tokens.next().unwrap_or_default().to_owned();
+                let credentials = found.get_or_insert(Credentials {
+                    username: String::new(),
+                    password: String::new(),
+                });
+                match token {
+                    "login" => credentials.username = value,
+                    _ => credentials.password = value,
+                }
+            }
+            "login" | "password" | "account" => {
+                tokens.next();
+            }
+            // A macro runs until the next blank line, which whitespace splitting cannot see.
+            "macdef" => break,
+            _ => {}
+        }
+    }
+    found
+}
+
+static SPACES: Mutex<Vec<Space>> = Mutex::new(Vec::new());
+
+/** Asks the user for credentials, or returns `None` if they decline. */
+type Ask = Box<dyn Fn(&Prompt) -> Option<Credentials>>;
+
+thread_local! {
+    static PROMPT: RefCell<Option<Ask>> = const { RefCell::new(None) };
+}
+
+/**
+ Ask `prompt` for credentials when a request on this thread needs some that the URL and
+ credentials file do not have. It returns `None` if the user declines.
+*/
+pub fn prompt_with(prompt: impl Fn(&Prompt) -> Option<Credentials> + 'static) {
+    PROMPT.with(|p| *p.borrow_mut() = Some(Box::new(prompt)));
+}
+
+fn ask(prompt: &Prompt) -> Option<Credentials> {
+    PROMPT.with(|p| p.borrow().as_ref().and_then(|ask| ask(prompt)))
+}
+
+/** What one request has tried so far while answering challenges. */
+#[derive(Default)]
+pub struct Attempt {
+    /** The space whose credentials went with the last request. */
+    sent: Option<Space>,
+    rejected: bool,
+    /** Whether a stale nonce was already answered; a second one counts as a rejection. */
+    retried_stale: bool,
+    tried_cache: bool,
+    tried_url: bool,
+    tried_file: bool,
+    prompts: usize,
+}
+
+impl Attempt {
+    /** The next credentials to try for `realm` at `url`, from each source in turn. */
+    fn next_credentials(&mut self, url: &URL, realm: &str) -> Option<Credentials> {
+        if !self.tried_cache {
+            self.tried_cache = true;
+            let spaces = SPACES.lock().unwrap();
+            let cached = spaces
+                .iter()
+                .find(|space| space.origin == origin(url) && space.realm == realm);
+            if let Some(space) = cached {
+                return Some(space.credentials.clone());
+            }
+        }
+        if !self.tried_url {
+            self.tried_url = true;
+            if !url.username.is_empty() {
+                return Some(Credentials {
+                    username: percent_decode(&url.username),
+                    password: percent_decode(url.password.as_deref().unwrap_or_default()),
+                });
+            }
+        }
+        if !self.tried_file {
+            self.tried_file = true;
+            let file = config::get().credentials_file;
+            if let Some(credentials) = file.and_then(|file| from_file(&file, &url.host)) {
+                return Some(credentials);
+            }
+        }
+        if self.prompts < MAX_PROMPTS {
+            self.prompts += 1;
+            return ask(&Prompt {
+                origin: origin(url),
+                realm: realm.to_owned(),
+                retry: self.rejected,
+            });
+        }
+        None
+    }
+}
+
+/**
+ The `Authorization` value to send with a request for `url` before being challenged, if
+ credentials for a space covering it were accepted earlier.
+*/
+pub fn preemptive(url: &URL, request: &Request, attempt: &mut Attempt) -> Option<String> {
+    let mut spaces = SPACES.lock().unwrap();
+    let space = spaces
+        .iter_mut()
+        .filter(|space| space.origin == origin(url) && url.path.starts_with(&space.directory))
+        .max_by_key(|space| space.directory.len())?;
+    let authorization = space.authorization(request.method.as_str(), request.target());
+    attempt.sent = Some(space.clone());
+    attempt.tried_cache = true;
+    Some(authorization)
+}
+
+/**
+ Answer a `401` response to `request` for `url`, returning the `Authorization` value to
+ retry with, or `None` when no supported challenge or credentials are left to try.
+*/
+pub fn respond(
+    url: &URL,
+    request: &Request,
+    response: &Response,
+    attempt: &mut Attempt,
+) -> Option<String> {
+    let challenge = response
+        .headers
+        .get_all("www-authenticate")
+        .iter()
+        .flat_map(|header| parse_challenges(header))
+        .filter(|challenge| challenge.rank().is_some())
+        .max_by_key(|challenge| challenge.rank())?;
+    let realm = challenge.param("realm").unwrap_or_default();
+    let stale = challenge
+        .param("stale")
+        .is_some_and(|stale| stale.eq_ignore_ascii_case("true"));
+    let credentials = match attempt.sent.take() {
+        // Only the nonce expired: the same credentials are answered again, unasked, once.
+        Some(sent) if stale && sent.realm == realm && !attempt.retried_stale => {
+            attempt.retried_stale = true;
+            Some(sent.credentials)
+        }
+        Some(sent) => {
+            attempt.rejected = true;
+            SPACES.lock().unwrap().retain(|space| {
+                !(space.origin == sent.origin
+                    && space.realm == sent.realm
+                    && space.credentials == sent.credentials)
+            });
+            None
+        }
+        None => None,
+    };
+    let credentials = credentials.or_else(|| attempt.next_credentials(url, realm))?;
+    let mut space = Space::new(url, &challenge, credentials);
+    let authorization = space.authorization(request.method.as_str(), request.target());
+    attempt.sent = Some(space);
+    Some(authorization)
+}
+
+/** The request went through: remember the credentials it carried for their space. */
+pub fn accepted(attempt: Attempt) {
+    let Some(mut accepted) = attempt.sent else {
+        return;
+    };
+    let mut spaces = SPACES.lock().unwrap();
+    let same =
+        |space: &&mut Space| space.origin == accepted.origin && space.realm == accepted.realm;
+    if let Some(space) = spaces.iter_mut().find(same) {
+        accepted.directory = common_directory(&space.directory, &accepted.directory);
+        *space = accepted;
+    } else {
+        spaces.push(accepted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::request::Method;
+
+    /** A `401` carrying `challenge`. */
+    fn unauthorized(url: &URL, challenge: &str) -> Response {
+        let mut response = Response::local(url, "text/html", Vec::new());
+        response.status = 401;
+        response.headers.insert("WWW-Authenticate", challenge);
+        response
+    }
+
+    #[test]
+    fn stale_nonce_is_retried_only_once() {
+        let url = URL::new("http://u:p@stale.test/x").unwrap();
+        let request = Request::new(Method::Get, "/x", "stale.test");
+        let stale = unauthorized(
+            &url,
+            r#"Digest realm="r", nonce="n", qop="auth", stale=true"#,
+        );
+        let mut attempt = Attempt::default();
+        // The credentials from the URL, then the same ones once more for the stale nonce.
+        assert!(respond(&url, &request, &stale, &mut attempt).is_some());
+        assert!(respond(&url, &request, &stale, &mut attempt).is_some());
+        assert!(respond(&url, &request, &stale, &mut attempt).is_none());
+    }
+
+    #[test]
+    fn md5_matches_rfc_1321() {
+        let md5 = |data: &str| hex(&Md5::digest(data.as_bytes()));
+        assert_eq!(md5(""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5("a"), "0cc175b9c0f1b6a831c399e269772661");
+        assert_eq!(md5("abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(md5("message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+        assert_eq!(
+            md5("abcdefghijklmnopqrstuvwxyz"),
+            "c3fcd3d76192e4007dfb496cca67e13b"
+        );
+        assert_eq!(
+            md5("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"),
+            "d174ab98d277d9f5a5611c2c9f419d9f"
+        );
+        assert_eq!(
+            md5("12345678901234567890123456789012345678901234567890123456789012345678901234567890"),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    /** The example of RFC 7616 section 3.9.1, answered with `algorithm`. */
+    fn rfc_7616_answer(algorithm: &str) -> String {
+        let url = URL::new("http://www.example.org/dir/index.html").unwrap();
+        let challenge = format!(
+            r#"Digest realm="http-auth@example.org", qop="auth, auth-int", algorithm={}, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#,
+            algorithm
+        );
+        let challenge = parse_challenges(&challenge).remove(0);
+        let credentials = Credentials {
+            username: "Mufasa".to_owned(),
+            password: "Circle of Life".to_owned(),
+        };
+        let mut space = Space::new(&url, &challenge, credentials);
+        space.answer(
+            "GET",
+            "/dir/index.html",
+            "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ",
+        )
+    }
+
+    #[test]
+    fn digest_matches_rfc_7616_example() {
+        let md5 = rfc_7616_answer("MD5");
+        assert!(md5.contains(r#"response="8ca523f5e9506fed4657c9700eebdbec""#));
+        assert!(md5.contains(r#"uri="/dir/index.html""#));
+        assert!(md5.contains(
+            r#"qop=auth, nc=00000001, cnonce="f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ""#
+        ));
+        assert!(md5.contains(r#"opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#));
+        let sha256 = rfc_7616_answer("SHA-256");
+        assert!(sha256.contains(
+            r#"response="753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1""#
+        ));
+    }
+}
@@ -0,0 +1,613 @@
+use crate::networking::config;
+use crate::networking::date;
+use crate::networking::error::Result;
+use crate::networking::request::{Method, Request};
+use crate::networking::response::{read_line, Response};
+use crate::networking::url::URL;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/** Statuses that may be stored without explicit freshness information (RFC 9111 section 4.2.2). */
+const CACHEABLE_STATUSES: [u16; 8] = [200, 203, 204, 300, 301, 308, 404, 410];
+/** Headers describing how the body was framed on the wire, which a stored entry does not keep. */
+const FRAMING_HEADERS: [&str; 3] = ["content-length", "content-encoding", "transfer-encoding"];
+
+/** The value of a `Cache-Control` directive: `Some("")` if it is present without one. */
+fn directive(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers
+        .get_all("cache-control")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .find_map(|item| {
+            let (key, value) = item.split_once('=').unwrap_or((item, ""));
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().trim_matches('"').to_owned())
+        })
+}
+
+/** The request headers a response varies on, lowercased. */
+fn vary_names(response: &Response) -> Vec<String> {
+    response
+        .headers
+        .get_all("vary")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/** A stored response and what is needed to decide whether it can still be used. */
+struct Entry {
+    response: Response,
+    /** Values of the request headers named by `Vary` when the response was stored. */
+    vary: Vec<(String, Option<String>)>,
+    /** When the response was received. */
+    stored: SystemTime,
+    /** When the entry was last looked up, so the least recently used goes first. */
+    used: SystemTime,
+}
+
+impl Entry {
+    fn new(request: &Request, response: Response, now: SystemTime) -> Self {
+        let vary = vary_names(&response)
+            .into_iter()
+            .map(|name| {
+                let value = request.header_value(&name);
+                (name, value)
+            })
+            .collect();
+        Self {
+            response,
+            vary,
+            stored: now,
+            used: now,
+        }
+    }
+
+    /** Whether a new request sends the same values for every `Vary` header. */
+    fn matches(&self, request: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| request.header_value(name) == *value)
+    }
+
+    /** How long the response stays fresh after it was generated (RFC 9111 section 4.2.1). */
+    fn freshness_lifetime(&self) -> Duration {
+        let headers = &self.response.headers;
+        if let Some(max_age) = directive(&self.response, "max-age") {
+            return Duration::from_secs(max_age.parse().unwrap_or(0));
+        }
+        let date = headers
+            .get("date")
+            .and_then(date::parse)
+            .unwrap_or(self.stored);
+        if let Some(expires) = headers.get("expires") {
+            // An invalid date, such as the common "Expires: 0", means already expired.
+            return date::parse(expires)
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or_default();
+        }
+        // Without explicit freshness, use a tenth of the time since the last change.
+        headers
+            .get("last-modified")
+            .and_then(date::parse)
+            .and_then(|modified| date.duration_since(modified).ok())
+            .map(|since| since / 10)
+            .unwrap_or_default()
+    }
+
+    /** Whether the entry can be served without asking the server first. */
+    fn is_fresh(&self, now: SystemTime) -> bool {
+        if directive(&self.response, "no-cache").is_some() {
+            return false;
+        }
+        let initial_age = self
+            .response
+            .headers
+            .get("age")
+            .and_then(|age| age.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let resident = now.duration_since(self.stored).unwrap_or_default();
+        initial_age.saturating_add(resident) < self.freshness_lifetime()
+    }
+
+    /** Take the headers of a `304 Not Modified` into the stored response. */
+    fn refresh(&mut self, not_modified: &Response, now: SystemTime) {
+        let mut updated = not_modified.headers.clone();
+        for name in FRAMING_HEADERS {
+            updated.remove(name);
+        }
+        let names: Vec<String> = updated.iter().map(|(name, _)| name.to_owned()).collect();
+        for name in names {
+            self.response.headers.remove(&name);
+        }
+        self.response.headers.extend(updated);
+        self.stored = now;
+    }
+
+    /**
+     Write the entry as its key, storage time and `Vary` values, a blank line, then the
+     response with the decoded body, so it can be read back with `Response::read`.
+    */
+    fn save(&self, key: &str, path: &Path) -> std::io::Result<()> {
+        let stored = self.stored.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut text = format!("{}\r\n{}\r\n", key, stored.as_secs());
+        for (name, value) in &self.vary {
+            match value {
+                Some(value) => text += &format!("{}: {}\r\n", name, value),
+                None => text += &format!("{}\r\n", name),
+            }
+        }
+        let response = &self.response;
+        text += &format!("\r\nHTTP/1.1 {} {}\r\n", response.status, response.reason);
+        for (name, value) in response.headers.iter() {
+            if !FRAMING_HEADERS.contains(&name) {
+                text += &format!("{}: {}\r\n", name, value);
+            }
+        }
+        text += &format!("content-length: {}\r\n\r\n", response.body.len());
+        let mut file = File::create(path)?;
+        file.write_all(text.as_bytes())?;
+        file.write_all(&response.body)
+    }
+
+    /** Read an entry written by `save`, checking that it belongs to `key`. */
+    fn load(key: &str, path: &Path) -> Result<Option<Self>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if read_line(&mut reader)? != key {
+            return Ok(None);
+        }
+        let stored = read_line(&mut reader)?.parse().unwrap_or(0);
+        let stored = date::after(UNIX_EPOCH, stored);
+        let mut vary = Vec::new();
+        loop {
+            let line = read_line(&mut reader)?;
+            if line.is_empty() {
+                break;
+            }
+            vary.push(match line.split_once(':') {
+                Some((name, value)) => (name.to_owned(), Some(value.trim().to_owned())),
+                None => (line, None),
+            });
+        }
+        let (response, _) = Response::read(&URL::new(key)?, Method::Get, &mut reader)?;
+        Ok(Some(Self {
+            response,
+            vary,
+            stored,
+            used: SystemTime::now(),
+        }))
+    }
+}
+
+/**
+ Responses kept by URL, one variant each. Entries live in memory and, when a directory
+ is configured, on disk, each side trimmed to the size limit.
+*/
+pub struct Cache {
+    entries: HashMap<String, Entry>,
+    dir: Option<PathBuf>,
+    max_size: u64,
+}
+
+impl Cache {
+    pub fn new(dir: Option<PathBuf>, max_size: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            dir,
+            max_size,
+        }
+    }
+
+    /** The file an entry for `key` is stored in, named by an FNV-1a hash of the key. */
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}", hash)))
+    }
+
+    /** The entry for `key`, from memory or else from disk. */
+    fn get(&mut self, key: &str) -> Option<&mut Entry> {
+        if !self.entries.contains_key(key) {
+            let entry = Entry::load(key, &self.path(key)?).ok()??;
+            self.entries.insert(key.to_owned(), entry);
+        }
+        self.entries.get_mut(key)
+    }
+
+    fn insert(&mut self, key: String, entry: Entry) {
+        self.entries.insert(key.clone(), entry);
+        self.persist(&key);
+        self.trim();
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+        if let Some(path) = self.path(key) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /** Write the entry for `key` to the cache directory, if there is one. */
+    fn persist(&self, key: &str) {
+        let (Some(entry), Some(path)) = (self.entries.get(key), self.path(key)) else {
+            return;
+        };
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| entry.save(key, &path));
+        if let Err(e) = saved {
+            eprintln!("Failed to write cache entry {}: {}", path.display(), e);
+        }
+    }
+
+    /** Evict the least recently used entries in memory, and the oldest files on disk, down to the limit. */
+    fn trim(&mut self) {
+        let mut total: u64 = self
+            .entries
+            .values()
+            .map(|e| e.response.body.len() as u64)
+            .sum();
+        while total > self.max_size {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            let entry = self.entries.remove(&oldest).unwrap();
+            total -= entry.response.body.len() as u64;
+        }
+
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let Ok(files) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut files: Vec<(PathBuf, u64, SystemTime)> = files
+            .filter_map(|file| {
+                let file = file.ok()?;
+                let metadata = file.metadata().ok()?;
+                Some((file.path(), metadata.len(), metadata.modified().ok()?))
+            })
+            .collect();
+        files.sort_by_key(|(_, _, modified)| *modified);
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        for (path, len, _) in files {
+            if total <= self.max_size {
+                break;
+            }
+            if fs::remove_file(path).is_ok() {
+                total -= len;
+            }
+        }
+    }
+}
+
+/** What to do with a request after consulting the cache. */
+pub enum Lookup {
+    /** A stored response that is still fresh; no request is needed. */
+    Fresh(Box<Response>),
+    /** Send this request, which carries validators when a stale entry can be revalidated. */
+    Send(Request),
+}
+
+impl Cache {
+    /** `lookup` at the time `now`. */
+    fn lookup(&mut self, url: &URL, request: Request, now: SystemTime) -> Lookup {
+        if request.method != Method::Get {
+            return Lookup::Send(request);
+        }
+        let Some(entry) = self
+            .get(&url.absolute_form())
+            .filter(|entry| entry.matches(&request))
+        else {
+            return Lookup::Send(request);
+        };
+        entry.used = now;
+        if entry.is_fresh(now) {
+            let mut response = entry.response.clone();
+            response.url = url.clone();
+            return Lookup::Fresh(Box::new(response));
+        }
+        let mut request = request;
+        if let Some(etag) = entry.response.headers.get("etag") {
+            request = request.header("If-None-Match", etag);
+        }
+        if let Some(modified) = entry.response.headers.get("last-modified") {
+            request = request.header("If-Modified-Since", modified);
+        }
+        Lookup::Send(request)
+    }
+
+    /** `update` at the time `now`. */
+    fn update(
+        &mut self,
+        url: &URL,
+        request: &Request,
+        response: Response,
+        now: SystemTime,
+    ) -> Response {
+        let key = url.absolute_form();
+        match request.method {
+            Method::Get => {}
+            Method::Head => return response,
+            _ => {
+                // A successful change through the URL makes what was stored out of date.
+                if response.status < 400 {
+                    self.remove(&key);
+                }
+                return response;
+            }
+        }
+        if response.status == 304 {
+            let Some(entry) = self.get(&key) else {
+                return response;
+            };
+            entry.refresh(&response, now);
+            let mut refreshed = entry.response.clone();
+            refreshed.url = url.clone();
+            self.persist(&key);
+            return refreshed;
+        }
+        // A diverted body was saved elsewhere, so there is nothing here worth keeping.
+        // `private` responses are meant for caches like this one, so only no-store counts.
+        let storable = !response.diverted
+            && CACHEABLE_STATUSES.contains(&response.status)
+            && directive(&response, "no-store").is_none()
+            && !vary_names(&response).iter().any(|name| name == "*");
+        if storable {
+            self.insert(key, Entry::new(request, response.clone(), now));
+        } else {
+            self.remove(&key);
+        }
+        response
+    }
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/** Run `f` on the shared cache, creating it from the configuration on first use. */
+fn with_cache<T>(f: impl FnOnce(&mut Cache) -> T) -> T {
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(|| {
+        let config = config::get();
+        Cache::new(config.cache_dir, config.cache_size)
+    });
+    f(cache)
+}
+
+/** Check the cache before sending `request` for `url`. Only GET requests are answered from it. */
+pub fn lookup(url: &URL, request: Request) -> Lookup {
+    with_cache(|cache| cache.lookup(url, request, SystemTime::now()))
+}
+
+/**
+ Record the server's `response` to `request`, returning the response to use.
+ A `304 Not Modified` is answered with the refreshed stored response.
+*/
+pub fn update(url: &URL, request: &Request, response: Response) -> Response {
+    with_cache(|cache| cache.update(url, request, response, SystemTime::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_age_is_stale_not_a_panic() {
+        let url = URL::new("http://example.com/").unwrap();
+        let mut response = Response::local(&url, "text/html", b"<p>hi</p>".to_vec());
+        response.headers.insert("Cache-Control", "max-age=60");
+        response.headers.insert("Age", "18446744073709551615");
+        let request = Request::new(Method::Get, "/", "example.com");
+        let now = SystemTime::now();
+        let entry = Entry::new(&request, response, now);
+        assert!(!entry.is_fresh(now + Duration::from_secs(1)));
+    }
+
+    fn url() -> URL {
+        URL::new("http://example.com/page").unwrap()
+    }
+
+    fn get() -> Request {
+        Request::new(Method::Get, "/page", "example.com")
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> Response {
+        let mut response = Response::local(&url(), "text/html", b"<p>hi</p>".to_vec());
+        response.status = status;
+        for (name, value) in headers {
+            response.headers.insert(name, value);
+        }
+        response
+    }
+
+    fn later(now: SystemTime, seconds: u64) -> SystemTime {
+        now + Duration::from_secs(seconds)
+    }
+
+    fn is_fresh(cache: &mut Cache, request: Request, now: SystemTime) -> bool {
+        matches!(cache.lookup(&url(), request, now), Lookup::Fresh(_))
+    }
+
+    /** The request a lookup says to send, which panics if the cache answered instead. */
+    fn sent(cache: &mut Cache, now: SystemTime) -> Request {
+        match cache.lookup(&url(), get(), now) {
+            Lookup::Send(request) => request,
+            Lookup::Fresh(_) => panic!("expected a request to be sent"),
+        }
+    }
+
+    #[test]
+    fn max_age_sets_freshness() {
+        let mut cache = Cache::new(None, u64::MAX);
+        let now = SystemTime::now();
+        cache.update(
+            &url(),
+            &get(),
+            response(200, &[("Cache-Control", "max-age=60")]),
+            now,
+        );
+        assert!(is_fresh(&mut cache, get(), later(now, 30)));
+        assert!(!is_fresh(&mut cache, get(), later(now, 61)));
+    }
+
+    #[test]
+    fn expires_sets_freshness_from_date() {
+        let mut cache = Cache::new(None, u64::MAX);
+        let now = SystemTime::now();
+        let date = date::format(now);
+        let expires = date::format(later(now, 100));
+        let headers = [("Date", date.as_str()), ("Expires", expires.as_str())];
+        cache.update(&url(), &get(), response(200, &headers), now);
+        assert!(is_fresh(&mut cache, get(), later(now, 50)));
+        assert!(!is_fresh(&mut cache, get(), later(now, 101)));
+
+        cache.update(&url(), &get(), response(200, &[("Expires", "0")]), now);
+        assert!(!is_fresh(&mut cache, get(), now));
+    }
+
+    #[test]
+    fn heuristic_freshness_is_a_tenth_of_the_age() {
+        let mut cache = Cache::new(None, u64::MAX);
+        let now = SystemTime::now();
+        let date = date::format(now);
+        let modified = date::format(now - Duration::from_secs(10 * 86_400));
+        let headers = [
+            ("Date", date.as_str()),
+            ("Last-Modified", modified.as_str()),
+        ];
+        cache.update(&url(), &get(), response(200, &headers), now);
+        assert!(is_fresh(&mut cache, get(), later(now, 43_200)));
+        assert!(!is_fresh(&mut cache, get(), later(now, 2 * 86_400)));
+
+        // Without any freshness information, every use is revalidated.
+        cache.update(&url(), &get(), response(200, &[]), now);
+        assert!(!is_fresh(&mut cache, get(), now));
+    }
+
+    #[test]
+    fn stale_entries_are_revalidated_and_refreshed_by_304() {
+        let mut cache = Cache::new(None, u64::MAX);
+        let now = SystemTime::now();
+        let modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+        let headers = [
+            ("Cache-Control", "max-age=0"),
+            ("ETag", "\"v1\""),
+            ("Last-Modified", modified),
+            ("X-Version", "1"),
+        ];
+        cache.update(&url(), &get(), response(200, &headers), now);
+
+        let request = sent(&mut cache, later(now, 1));
+        assert_eq!(
+            request.header_value("If-None-Match").as_deref(),
+            Some("\"v1\"")
+        );
+        assert_eq!(
+            request.header_value("If-Modified-Since").as_deref(),
+            Some(modified)
+        );
+
+        let mut not_modified =
+            response(304, &[("Cache-Control", "max-age=100"), ("X-Version", "2")]);
+        not_modified.reason = "Not Modified".to_owned();
+        not_modified.body.clear();
+        not_modified.headers.insert("Content-Length", "0");
+        let refreshed = cache.update(&url(), &request, not_modified, later(now, 1));
+        assert_eq!(refreshed.status, 200);
+        assert_eq!(refreshed.body, b"<p>hi</p>");
+        assert_eq!(refreshed.headers.get("x-version"), Some("2"));
+        assert_eq!(refreshed.headers.get("etag"), Some("\"v1\""));
+        assert_eq!(refreshed.headers.get("content-length"), Some("9"));
+
+        match cache.lookup(&url(), get(), later(now, 50)) {
+            Lookup::Fresh(response) => assert_eq!(response.headers.get("x-version"), Some("2")),
+            Lookup::Send(_) => panic!("the refreshed entry should be fresh"),
+        }
+    }
+
+    #[test]
+    fn vary_mismatch_is_not_served() {
+        let mut cache = Cache::new(None, u64::MAX);
+        let now = SystemTime::now();
+        let english = get().header("Accept-Language", "en");
+        let headers = [("Cache-Control", "max-age=60"), ("Vary", "Accept-Language")];
+        cache.update(&url(), &english, response(200, &headers), now);
+        assert!(is_fresh(&mut cache, english.clone(), now));
+        assert!(!is_fresh(
+            &mut cache,
+            get().header("Accept-Language", "fr"),
+            now
+        ));
+        assert!(!is_fresh(&mut cache, get(), now));
+
+        let headers = [("Cache-Control", "max-age=60"), ("Vary", "*")];
+        cache.update(&url(), &english, response(200, &headers), now);
+        assert!(!is_fresh(&mut cache, english, now));
+    }
+
+    #[test]
+    fn no_store_is_not_stored_but_private_is() {
+        let mut cache = Cache::new(None, u64::MAX);
+        let now = SystemTime::now();
+        cache.update(
+            &url(),
+            &get(),
+            response(200, &[("Cache-Control", "private, max-age=60")]),
+            now,
+        );
+        assert!(is_fresh(&mut cache, get(), now));
+
+        let headers = [("Cache-Control", "no-store, max-age=60")];
+        cache.update(&url(), &get(), response(200, &headers), now);
+        assert!(!is_fresh(&mut cache, get(), now));
+        assert!(sent(&mut cache, now)
+            .header_value("If-None-Match")
+            .is_none());
+    }
+
+    #[test]
+    fn unsafe_methods_invalidate() {
+        let now = SystemTime::now();
+        for method in [Method::Post, Method::Put, Method::Delete] {
+            let mut cache = Cache::new(None, u64::MAX);
+            cache.update(
+                &url(),
+                &get(),
+                response(200, &[("Cache-Control", "max-age=60")]),
+                now,
+            );
+
+            let change = Request::new(method, "/page", "example.com");
+            cache.update(&url(), &change, response(500, &[]), now);
+            assert!(
+                is_fresh(&mut cache, get(), now),
+                "a failed change keeps the entry"
+            );
+
+            cache.update(&url(), &change, response(200, &[]), now);
+            assert!(
+                !is_fresh(&mut cache, get(), now),
+                "a {:?} leaves it out of date",
+                change.method
+            );
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use encoding_rs::{Decoder, Encoding, UTF_8};
+
+/** How many bytes of the body are searched for a `<meta>` charset declaration. */
+const PRESCAN_LIMIT: usize = 1024;
+
+/** The `charset` parameter of a `Content-Type` value, if it names a known encoding. */
+fn from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes())
+    })
+}
+
+/** Split the inside of a tag into lowercased attribute names and their unquoted values. */
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace() || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_lowercase();
+        rest = rest[name_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    value = inner[..end].to_owned();
+                    rest = inner.get(end + 1..).unwrap_or_default().trim_start();
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    value = after[..end].to_owned();
+                    rest = after[end..].trim_start();
+                }
+            }
+        } else {
+            rest = rest.trim_start_matches('/').trim_start();
+        }
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+    }
+    attributes
+}
+
+/** Look for `<meta charset>` or `<meta http-equiv="content-type">` near the start of an HTML body. */
+fn prescan(body: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(PRESCAN_LIMIT)]);
+    let lower = head.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find("<meta") {
+        let start = from + start + "<meta".len();
+        let end = lower[start..].find('>').map_or(lower.len(), |i| start + i);
+        from = end;
+        let attributes = attributes(&head[start..end]);
+        let get = |key: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        let encoding = if let Some(charset) = get("charset") {
+            Encoding::for_label(charset.trim().as_bytes())
+        } else if get("http-equiv").is_some_and(|v| v.eq_ignore_ascii_case("content-type")) {
+            get("content").and_then(|content| from_content_type(&format!(";{}", content)))
+        } else {
+            None
+        };
+        if let Some(encoding) = encoding {
+            // A document that could be read as ASCII to find this tag cannot really be UTF-16.
+            return Some(encoding.output_encoding());
+        }
+    }
+    None
+}
+
+/** Whether a `Content-Type` value is HTML. A missing one is taken to be. */
+pub fn is_html(content_type: Option<&str>) -> bool {
+    content_type.is_none_or(|value| {
+        let mime = value.split(';').next().unwrap_or_default().trim();
+        mime.eq_ignore_ascii_case("text/html") || mime.eq_ignore_ascii_case("application/xhtml+xml")
+    })
+}
+
+/**
+ Whether a `Content-Type` value is something the browser shows itself: HTML, or text it can
+ display as is. A missing one is taken to be HTML.
+*/
+pub fn is_text(content_type: Option<&str>) -> bool {
+    let Some(value) = content_type else {
+        return true;
+    };
+    let mime = value
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime.as_str(),
+            "application/json" | "application/xml" | "application/javascript"
+        )
+}
+
+/**
+ Pick the encoding of a response body. A byte-order mark wins, as the HTML spec requires,
+ then the `Content-Type` charset, then a `<meta>` prescan for HTML, falling back to UTF-8.
+*/
+pub fn detect(content_type: Option<&str>, body: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return encoding;
+    }
+    if let Some(encoding) = content_type.and_then(from_content_type) {
+        return encoding;
+    }
+    if is_html(content_type) {
+        if let Some(encoding) = prescan(body) {
+            return encoding;
+        }
+    }
+    UTF_8
+}
+
+/** Decode `body` to text using the detected encoding, replacing malformed sequences. */
+pub fn decode(content_type: Option<&str>, body: &[u8]) -> String {
+    let encoding = detect(content_type, body);
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+/**
+ Decodes a body to text as it arrives. The encoding is picked as `detect` does, once enough
+ of the body for the `<meta>` prescan has been seen or the body has ended.
+*/
+pub struct TextDecoder {
+    content_type: Option<String>,
+    pending: Vec<u8>,
+    decoder: Option<Decoder>,
+}
+
+impl TextDecoder {
+    pub fn new(content_type: Option<&str>) -> Self {
+        Self {
+            content_type: content_type.map(str::to_owned),
+            pending: Vec::new(),
+            decoder: None,
+        }
+    }
+
+    /** Decode the next piece of the body, returning the text it completes. */
+    pub fn decode(&mut self, data: &[u8]) -> String {
+        if self.decoder.is_some() {
+            return self.run(data, false);
+        }
+        self.pending.extend_from_slice(data);
+        if self.pending.len() < PRESCAN_LIMIT {
+            return String::new();
+        }
+        self.start(false)
+    }
+
+    /** The body has ended: return whatever text is still held back. */
+    pub fn finish(&mut self) -> String {
+        if self.decoder.is_some() {
+            return self.run(&[], true);
+        }
+        self.start(true)
+    }
+
+    fn start(&mut self, last: bool) -> String {
+        let encoding = detect(self.content_type.as_deref(), &self.pending);
+        self.decoder = Some(encoding.new_decoder());
+        let pending = std::mem::take(&mut self.pending);
+        self.run(&pending, last)
+    }
+
+    fn run(&mut self, data: &[u8], last: bool) -> String {
+        let decoder = self
+            .decoder
+            .as_mut()
+            .expect("decoder is picked before running");
+        let capacity = decoder
+            .max_utf8_buffer_length(data.len())
+            .unwrap_or(data.len() * 3 + 16);
+        let mut text = String::with_capacity(capacity);
+        let _ = decoder.decode_to_string(data, &mut text, last);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+    /** Decode `body` through a `TextDecoder` fed `size` bytes at a time. */
+    fn stream(content_type: Option<&str>, body: &[u8], size: usize) -> String {
+        let mut decoder = TextDecoder::new(content_type);
+        let mut text = String::new();
+        for piece in body.chunks(size) {
+            text += &decoder.decode(piece);
+        }
+        text + &decoder.finish()
+    }
+
+    #[test]
+    fn bom_beats_header_and_meta() {
+        // The HTML spec puts the byte-order mark first, ahead of the transport layer.
+        let body = b"\xEF\xBB\xBF<meta charset=shift_jis>";
+        assert_eq!(detect(Some("text/html; charset=iso-8859-1"), body), UTF_8);
+        assert_eq!(detect(None, b"\xFF\xFEa\0"), UTF_16LE);
+        assert_eq!(
+            detect(Some("text/html; charset=utf-8"), b"\xFE\xFF\0a"),
+            UTF_16BE
+        );
+    }
+
+    #[test]
+    fn header_beats_meta() {
+        let body = b"<meta charset=shift_jis>";
+        assert_eq!(
+            detect(Some("text/html; charset=\"ISO-8859-1\""), body),
+            WINDOWS_1252
+        );
+        assert_eq!(detect(Some("text/html; charset=bogus"), body), SHIFT_JIS);
+        assert_eq!(detect(Some("text/html"), body), SHIFT_JIS);
+    }
+
+    #[test]
+    fn meta_is_only_used_for_html() {
+        let body = b"<meta charset=shift_jis>";
+        assert_eq!(detect(Some("text/plain"), body), UTF_8);
+        assert_eq!(detect(None, body), SHIFT_JIS);
+        assert_eq!(detect(None, b"plain"), UTF_8);
+    }
+
+    #[test]
+    fn prescan_finds_declarations() {
+        assert_eq!(prescan(b"<META CHARSET='Shift_JIS'>"), Some(SHIFT_JIS));
+        assert_eq!(
+            prescan(
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-1\">"
+            ),
+            Some(WINDOWS_1252)
+        );
+        assert_eq!(
+            prescan(b"<meta name=viewport content=x><meta charset=\"shift_jis\"/>"),
+            Some(SHIFT_JIS)
+        );
+        // A page readable as ASCII cannot really be UTF-16.
+        assert_eq!(prescan(b"<meta charset=utf-16le>"), Some(UTF_8));
+        assert_eq!(prescan(b"<meta charset=nonsense>"), None);
+        assert_eq!(prescan(b"<meta content=\"charset=shift_jis\">"), None);
+
+        let mut late = vec![b' '; PRESCAN_LIMIT];
+        late.extend_from_slice(b"<meta charset=shift_jis>");
+        assert_eq!(prescan(&late), None);
+    }
+
+    #[test]
+    fn streaming_matches_whole_decoding() {
+        let body = "<p>caf\u{e9} \u{65e5}\u{672c} \u{1F600}</p>".repeat(100);
+        for size in [1, 2, 3, 7, 1024, body.len()] {
+            assert_eq!(stream(Some("text/html"), body.as_bytes(), size), body);
+        }
+    }
+
+    #[test]
+    fn streaming_joins_split_sequences_after_the_prescan() {
+        let mut body = vec![b' '; PRESCAN_LIMIT];
+        body.extend_from_slice("\u{1F600}".as_bytes());
+        let mut decoder = TextDecoder::new(Some("text/plain; charset=utf-8"));
+        let mut text = decoder.decode(&body[..PRESCAN_LIMIT + 1]);
+        assert_eq!(text.len(), PRESCAN_LIMIT);
+        text += &decoder.decode(&body[PRESCAN_LIMIT + 1..PRESCAN_LIMIT + 3]);
+        assert_eq!(text.len(), PRESCAN_LIMIT);
+        text += &decoder.decode(&body[PRESCAN_LIMIT + 3..]);
+        assert_eq!(text.trim_start(), "\u{1F600}");
+        assert_eq!(decoder.finish(), "");
+    }
+
+    #[test]
+    fn streaming_uses_the_detected_encoding() {
+        let (body, _, _) = SHIFT_JIS.encode("<meta charset=shift_jis>\u{65e5}\u{672c}");
+        assert_eq!(
+            stream(None, &body, 1),
+            "<meta charset=shift_jis>\u{65e5}\u{672c}"
+        );
+        assert_eq!(stream(None, b"\xEF\xBB\xBFhi", 1), "hi");
+        assert_eq!(stream(Some("text/plain"), b"a\xF0\x9F", 1), "a\u{FFFD}");
+    }
+}
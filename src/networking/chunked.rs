@@ -0,0 +1,43 @@
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::response::{copy_body, read_line, HeaderMap};
+use std::io::{BufRead, Write};
+
+/**
+ Decode a `Transfer-Encoding: chunked` body from `reader`, writing the data to `out` one
+ chunk at a time. Chunk extensions are ignored; trailer fields are returned.
+ Reading stops right after the final CRLF, so the rest of the stream is left untouched.
+*/
+pub fn decode<R: BufRead, W: Write>(reader: &mut R, out: &mut W) -> Result<HeaderMap> {
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| NetworkError::MalformedChunk(line.clone()))?;
+        if size == 0 {
+            break;
+        }
+        copy_body(reader, Some(size), out).map_err(|e| match e {
+            NetworkError::Io(_) => {
+                NetworkError::MalformedChunk(format!("chunk shorter than {} bytes", size))
+            }
+            e => e,
+        })?;
+        if !read_line(reader)?.is_empty() {
+            return Err(NetworkError::MalformedChunk(
+                "missing CRLF after chunk data".to_owned(),
+            ));
+        }
+    }
+    let mut trailers = HeaderMap::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let (k, v) = line
+            .split_once(':')
+            .ok_or_else(|| NetworkError::MalformedHeader(line.clone()))?;
+        trailers.insert(k, v);
+    }
+    Ok(trailers)
+}
@@ -0,0 +1,339 @@
+use crate::networking::error::{NetworkError, Result};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write;
+use std::io::{self, Read, Write};
+
+/** Value sent in `Accept-Encoding` on every request. */
+pub const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+/** Why a body was cut off: it decoded to more than `limit` bytes. */
+fn too_large(limit: u64) -> io::Error {
+    io::Error::other(format!("decoded body is larger than {} bytes", limit))
+}
+
+/**
+ Undo a `Content-Encoding` header value such as `gzip` or `deflate, br`, failing if any step
+ produces more than `limit` bytes. Codings are listed in the order they were applied, so they
+ are removed last to first.
+*/
+pub fn decode(content_encoding: &str, body: Vec<u8>, limit: u64) -> Result<Vec<u8>> {
+    let mut body = body;
+    for coding in content_encoding.rsplit(',') {
+        let coding = coding.trim().to_lowercase();
+        body = match coding.as_str() {
+            "" | "identity" => body,
+            "gzip" | "x-gzip" => read_all(GzDecoder::new(&body[..]), &coding, limit)?,
+            // Some servers send raw deflate data instead of the zlib wrapper the spec asks for.
+            "deflate" => read_all(ZlibDecoder::new(&body[..]), &coding, limit)
+                .or_else(|_| read_all(DeflateDecoder::new(&body[..]), &coding, limit))?,
+            "br" => read_all(
+                brotli_decompressor::Decompressor::new(&body[..], 4096),
+                &coding,
+                limit,
+            )?,
+            _ => return Err(NetworkError::UnsupportedEncoding(coding)),
+        };
+    }
+    Ok(body)
+}
+
+fn read_all<R: Read>(reader: R, coding: &str, limit: u64) -> Result<Vec<u8>> {
+    let failed = |e: io::Error| NetworkError::Decompress(format!("{}: {}", coding, e));
+    let mut out = Vec::new();
+    reader
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut out)
+        .map_err(failed)?;
+    if out.len() as u64 > limit {
+        return Err(failed(too_large(limit)));
+    }
+    Ok(out)
+}
+
+/** Passes writes on to `W` until more than `limit` bytes have gone through. */
+struct Limited<W: Write> {
+    inner: W,
+    written: u64,
+    limit: u64,
+}
+
+impl<W: Write> Write for Limited<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written.saturating_add(buf.len() as u64) > self.limit {
+            return Err(too_large(self.limit));
+        }
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/** Where a `Decoder` is in undoing its coding. */
+enum State<W: Write> {
+    Identity(W),
+    /** A single coding, before any of the body has arrived. */
+    Pending(Limited<W>),
+    Gzip(write::GzDecoder<Limited<W>>),
+    Zlib(write::ZlibDecoder<Limited<W>>),
+    RawDeflate(write::DeflateDecoder<Limited<W>>),
+    Brotli(Box<brotli_decompressor::DecompressorWriter<Limited<W>>>),
+    /** Several codings are stacked: collect the body and undo them all at the end. */
+    Buffered(Vec<u8>, W),
+}
+
+/**
+ Undoes a `Content-Encoding` while the body is still arriving, writing decoded bytes on to
+ `W` as soon as they are available, so a page can be shown before it has all been received.
+ A coded body may decode to at most `limit` bytes, so a small decompression bomb cannot fill
+ memory or disk.
+*/
+pub struct Decoder<W: Write> {
+    coding: String,
+    state: Option<State<W>>,
+    encoded_size: usize,
+    limit: u64,
+}
+
+impl<W: Write> Decoder<W> {
+    pub fn new(content_encoding: &str, out: W, limit: u64) -> Result<Self> {
+        let codings: Vec<String> = content_encoding
+            .split(',')
+            .map(|coding| coding.trim().to_lowercase())
+            .filter(|coding| !coding.is_empty() && coding != "identity")
+            .collect();
+        for coding in &codings {
+            if !["gzip", "x-gzip", "deflate", "br"].contains(&coding.as_str()) {
+                return Err(NetworkError::UnsupportedEncoding(coding.to_owned()));
+            }
+        }
+        let state = match codings.len() {
+            0 => State::Identity(out),
+            1 => State::Pending(Limited {
+                inner: out,
+                written: 0,
+                limit,
+            }),
+            _ => State::Buffered(Vec::new(), out),
+        };
+        Ok(Self {
+            coding: codings.join(", "),
+            state: Some(state),
+            encoded_size: 0,
+            limit,
+        })
+    }
+
+    /** Body bytes taken in so far, before decoding. */
+    pub fn encoded_size(&self) -> usize {
+        self.encoded_size
+    }
+
+    /** Check that the body ended where its coding says it should, and hand back the output. */
+    pub fn finish(self) -> Result<W> {
+        let coding = self.coding;
+        let failed = |e: io::Error| NetworkError::Decompress(format!("{}: {}", coding, e));
+        match self.state.expect("state is only taken while switching") {
+            // An empty body has nothing to decode, whatever the header says.
+            State::Identity(out) => Ok(out),
+            State::Pending(out) => Ok(out.inner),
+            State::Gzip(decoder) => decoder.finish().map(|out| out.inner).map_err(failed),
+            State::Zlib(decoder) => decoder.finish().map(|out| out.inner).map_err(failed),
+            State::RawDeflate(decoder) => decoder.finish().map(|out| out.inner).map_err(failed),
+            State::Brotli(decoder) => decoder
+                .into_inner()
+                .map(|out| out.inner)
+                .map_err(|_| failed(io::ErrorKind::UnexpectedEof.into())),
+            State::Buffered(body, mut out) => {
+                out.write_all(&decode(&coding, body, self.limit)?)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(State::Pending(_)) = self.state {
+            let Some(State::Pending(out)) = self.state.take() else {
+                unreachable!()
+            };
+            self.state = Some(match self.coding.as_str() {
+                "gzip" | "x-gzip" => State::Gzip(write::GzDecoder::new(out)),
+                // Some servers send raw deflate data instead of the zlib wrapper the spec asks
+                // for. A zlib stream starts with a byte naming deflate (8) and a window of at
+                // most 32K.
+                "deflate" if buf[0] & 0x0f == 8 && buf[0] >> 4 <= 7 => {
+                    State::Zlib(write::ZlibDecoder::new(out))
+                }
+                "deflate" => State::RawDeflate(write::DeflateDecoder::new(out)),
+                _ => State::Brotli(Box::new(brotli_decompressor::DecompressorWriter::new(
+                    out, 4096,
+                ))),
+            });
+        }
+        let written = match self
+            .state
+            .as_mut()
+            .expect("state is only taken while switching")
+        {
+            State::Identity(out) => out.write(buf),
+            State::Pending(_) => unreachable!(),
+            State::Gzip(decoder) => decoder.write(buf),
+            State::Zlib(decoder) => decoder.write(buf),
+            State::RawDeflate(decoder) => decoder.write(buf),
+            State::Brotli(decoder) => decoder.write(buf),
+            State::Buffered(body, _) => body.write(buf),
+        }
+        .map_err(|e| {
+            io::Error::other(NetworkError::Decompress(format!("{}: {}", self.coding, e)))
+        })?;
+        self.encoded_size += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+
+    const LIMIT: u64 = 1024 * 1024;
+
+    fn text() -> Vec<u8> {
+        b"The quick brown fox jumps over the lazy dog. ".repeat(200)
+    }
+
+    fn encode(coding: &str, body: &[u8]) -> Vec<u8> {
+        match coding {
+            "gzip" => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).unwrap();
+                encoder.finish().unwrap()
+            }
+            "zlib" => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).unwrap();
+                encoder.finish().unwrap()
+            }
+            "raw" => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).unwrap();
+                encoder.finish().unwrap()
+            }
+            "br" => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 9, 22);
+                encoder.write_all(body).unwrap();
+                encoder.into_inner()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /** Decode `body` through a `Decoder`, fed a few bytes at a time as a network read would. */
+    fn stream(content_encoding: &str, body: &[u8], limit: u64) -> Result<Vec<u8>> {
+        let mut decoder = Decoder::new(content_encoding, Vec::new(), limit)?;
+        for piece in body.chunks(7) {
+            decoder.write_all(piece)?;
+        }
+        assert_eq!(decoder.encoded_size(), body.len());
+        decoder.finish()
+    }
+
+    /** Both ways of decoding, which must agree. */
+    fn both(content_encoding: &str, body: &[u8]) -> Vec<u8> {
+        let whole = decode(content_encoding, body.to_vec(), LIMIT).unwrap();
+        assert_eq!(stream(content_encoding, body, LIMIT).unwrap(), whole);
+        whole
+    }
+
+    #[test]
+    fn decodes_gzip() {
+        assert_eq!(both("gzip", &encode("gzip", &text())), text());
+        assert_eq!(both("X-Gzip", &encode("gzip", &text())), text());
+    }
+
+    #[test]
+    fn deflate_accepts_zlib_and_raw_data() {
+        assert_eq!(both("deflate", &encode("zlib", &text())), text());
+        assert_eq!(both("deflate", &encode("raw", &text())), text());
+    }
+
+    #[test]
+    fn decodes_brotli() {
+        assert_eq!(both("br", &encode("br", &text())), text());
+    }
+
+    #[test]
+    fn stacked_codings_are_removed_last_to_first() {
+        let body = encode("br", &encode("gzip", &text()));
+        assert_eq!(both("gzip, br", &body), text());
+        assert_eq!(both("identity, gzip , br", &body), text());
+        assert!(decode("br, gzip", body.clone(), LIMIT).is_err());
+        assert!(stream("br, gzip", &body, LIMIT).is_err());
+    }
+
+    #[test]
+    fn identity_and_empty_bodies_pass_through() {
+        assert_eq!(both("", &text()), text());
+        assert_eq!(both("identity", &text()), text());
+        assert_eq!(stream("gzip", b"", LIMIT).unwrap(), b"");
+    }
+
+    #[test]
+    fn unknown_and_broken_codings_fail() {
+        assert!(matches!(
+            Decoder::new("gzip, compress", Vec::new(), LIMIT),
+            Err(NetworkError::UnsupportedEncoding(coding)) if coding == "compress"
+        ));
+        assert!(matches!(
+            decode("zstd", text(), LIMIT),
+            Err(NetworkError::UnsupportedEncoding(_))
+        ));
+        let body = encode("gzip", &text());
+        let truncated = &body[..body.len() / 2];
+        assert!(matches!(
+            decode("gzip", truncated.to_vec(), LIMIT),
+            Err(NetworkError::Decompress(_))
+        ));
+        assert!(stream("gzip", truncated, LIMIT).is_err());
+    }
+
+    #[test]
+    fn decompression_bombs_are_refused() {
+        let zeros = vec![0; LIMIT as usize * 8];
+        for coding in ["gzip", "br"] {
+            let bomb = encode(coding, &zeros);
+            assert!(bomb.len() < zeros.len() / 100);
+            assert!(matches!(
+                decode(coding, bomb.clone(), LIMIT),
+                Err(NetworkError::Decompress(reason)) if reason.contains("larger than")
+            ));
+            let error = stream(coding, &bomb, LIMIT).unwrap_err();
+            assert!(error.to_string().contains("larger than"), "{}", error);
+        }
+        let stacked = encode("br", &encode("gzip", &zeros));
+        assert!(decode("gzip, br", stacked.clone(), LIMIT).is_err());
+        assert!(stream("gzip, br", &stacked, LIMIT).is_err());
+    }
+
+    #[test]
+    fn bodies_up_to_the_limit_are_kept() {
+        let zeros = vec![0; LIMIT as usize];
+        assert_eq!(both("gzip", &encode("gzip", &zeros)), zeros);
+        let bomb = encode("gzip", &zeros);
+        assert!(decode("gzip", bomb.clone(), LIMIT - 1).is_err());
+        assert!(stream("gzip", &bomb, LIMIT - 1).is_err());
+    }
+}
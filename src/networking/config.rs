@@ -0,0 +1,92 @@
+use crate::networking::archive;
+use crate::networking::proxy::ProxySettings;
+use crate::networking::request::Version;
+use crate::networking::tls::TlsSettings;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/** Settings shared by every request the networking module makes. */
+#[derive(Debug, Clone)]
+pub struct Config {
+    /** How many redirects to follow before giving up. */
+    pub max_redirects: usize,
+    /** Where persistent cookies are loaded from and saved to. `None` keeps them in memory. */
+    pub cookie_file: Option<PathBuf>,
+    /** Where hosts that sent `Strict-Transport-Security` are remembered between runs. */
+    pub hsts_file: Option<PathBuf>,
+    /** Whether the built-in list of HTTPS-only hosts applies on top of the ones seen. */
+    pub hsts_preload: bool,
+    /** A `.netrc`-style file of logins to answer authentication challenges with. */
+    pub credentials_file: Option<PathBuf>,
+    /** Where downloads are saved. `None` uses the user's download directory. */
+    pub downloads_dir: Option<PathBuf>,
+    /** Where cached responses are kept between runs. `None` caches in memory only. */
+    pub cache_dir: Option<PathBuf>,
+    /** Most bytes of response bodies the cache holds, in memory and on disk alike. */
+    pub cache_size: u64,
+    /** Most bytes a compressed response body may expand to before it is refused. */
+    pub max_decoded_size: u64,
+    /** How long to wait for a TCP connection and TLS handshake. `None` waits forever. */
+    pub connect_timeout: Option<Duration>,
+    /** How long a single read or write may stall before the request fails. */
+    pub read_timeout: Option<Duration>,
+    /**
+     How long a whole request may take, redirects included. A body saved as a download
+     only has to keep arriving within `read_timeout`.
+    */
+    pub total_timeout: Option<Duration>,
+    /** Proxies to send requests through, usually taken from the environment. */
+    pub proxy: ProxySettings,
+    /** Extra trusted roots, a client certificate, or insecure mode for HTTPS. */
+    pub tls: TlsSettings,
+    /** The protocol version written in the request line. */
+    pub http_version: Version,
+    /** Record every exchange to a WARC file, or answer requests from one instead. */
+    pub archive: Option<archive::Mode>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_redirects: 20,
+            cookie_file: None,
+            hsts_file: None,
+            hsts_preload: true,
+            credentials_file: None,
+            downloads_dir: None,
+            cache_dir: None,
+            cache_size: 64 * 1024 * 1024,
+            max_decoded_size: 1024 * 1024 * 1024,
+            connect_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Some(Duration::from_secs(30)),
+            total_timeout: Some(Duration::from_secs(120)),
+            proxy: ProxySettings::default(),
+            tls: TlsSettings::default(),
+            http_version: Version::Http11,
+            archive: None,
+        }
+    }
+}
+
+static CONFIG: RwLock<Option<Config>> = RwLock::new(None);
+
+/** The active configuration, or the defaults if none was set. */
+pub fn get() -> Config {
+    CONFIG.read().unwrap().clone().unwrap_or_default()
+}
+
+/** Replace the active configuration. */
+pub fn set(config: Config) {
+    *CONFIG.write().unwrap() = Some(config);
+}
+
+/** Broust's directory under the user's config directory, such as `~/.config/broust`. */
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("broust"))
+}
+
+/** Broust's directory under the user's cache directory, such as `~/.cache/broust`. */
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("broust"))
+}
@@ -0,0 +1,481 @@
+use crate::networking::config;
+use crate::networking::date;
+use crate::networking::request::Method;
+use crate::networking::url::URL;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "strict" => Some(SameSite::Strict),
+            "lax" => Some(SameSite::Lax),
+            "none" => Some(SameSite::None),
+            _ => None,
+        }
+    }
+}
+
+/** One stored cookie, as described in RFC 6265 section 5.3. */
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    /** Set when the cookie had no Domain attribute and only matches its origin host. */
+    pub host_only: bool,
+    pub path: String,
+    /** `None` for session cookies, which are dropped when the browser exits. */
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSite,
+    created: SystemTime,
+}
+
+impl Cookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+    fn matches(&self, url: &URL) -> bool {
+        let host = url.host.as_str();
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(host, &self.domain)
+        };
+        domain_ok && path_match(&url.path, &self.path) && (!self.secure || url.scheme == "https")
+    }
+}
+
+/** Whether `host` is `domain` or a subdomain of it (section 5.1.3). */
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<std::net::IpAddr>().is_err())
+}
+
+/**
+ Multi-label suffixes under which anyone can register a name. A short sample of the public
+ suffix list, which is not bundled; every single-label domain counts as well.
+*/
+const PUBLIC_SUFFIXES: [&str; 16] = [
+    "co.uk",
+    "org.uk",
+    "ac.uk",
+    "gov.uk",
+    "com.au",
+    "net.au",
+    "org.au",
+    "co.nz",
+    "co.jp",
+    "ne.jp",
+    "com.br",
+    "com.cn",
+    "co.in",
+    "co.za",
+    "github.io",
+    "blogspot.com",
+];
+
+/** Whether cookies may not be set for all of `domain`, such as `com` or `co.uk`. */
+fn is_public_suffix(domain: &str) -> bool {
+    !domain.contains('.') || PUBLIC_SUFFIXES.contains(&domain)
+}
+
+/** Whether a request path falls under a cookie path (section 5.1.4). */
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/** The directory of the request path, used when Set-Cookie has no Path (section 5.1.4). */
+fn default_path(url: &URL) -> String {
+    match url.path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(i) => url.path[..i].to_owned(),
+    }
+}
+
+/**
+ The registrable domain of `host`: its public suffix plus one more label, so that
+ `a.example.co.uk` and `b.example.co.uk` are the same site but `a.co.uk` and `b.co.uk` are not.
+*/
+fn site(host: &str) -> &str {
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return host;
+    }
+    // The longest listed suffix wins, which is found first walking labels from the left.
+    let mut rest = host;
+    let mut site = host;
+    while let Some((_, parent)) = rest.split_once('.') {
+        if is_public_suffix(parent) {
+            return site;
+        }
+        site = parent;
+        rest = parent;
+    }
+    host
+}
+
+/** Cookies kept across requests, keyed by name, domain and path. */
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /** Apply one `Set-Cookie` header received from `url` (section 5.2 and 5.3). */
+    pub fn store(&mut self, url: &URL, set_cookie: &str) {
+        let mut parts = set_cookie.split(';');
+        let Some((name, value)) = parts.next().and_then(|pair| pair.split_once('=')) else {
+            return;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() {
+            return;
+        }
+        let now = SystemTime::now();
+        let mut cookie = Cookie {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: url.host.clone(),
+            host_only: true,
+            path: default_path(url),
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: SameSite::Lax,
+            created: now,
+        };
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "expires" => {
+                    if let Some(expires) = date::parse(value) {
+                        cookie.expires = Some(expires);
+                    }
+                }
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(seconds);
+                    }
+                }
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_lowercase();
+                    if !domain.is_empty() {
+                        if !domain_match(&url.host, &domain) {
+                            return;
+                        }
+                        // A public suffix only names the host itself, and no other (step 5).
+                        if is_public_suffix(&domain) {
+                            if domain != url.host {
+                                return;
+                            }
+                        } else {
+                            cookie.domain = domain;
+                            cookie.host_only = false;
+                        }
+                    }
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_owned(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    if let Some(same_site) = SameSite::parse(value) {
+                        cookie.same_site = same_site;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Max-Age wins over Expires, and zero or less means "delete now".
+        if let Some(seconds) = max_age {
+            cookie.expires = Some(if seconds <= 0 {
+                UNIX_EPOCH
+            } else {
+                date::after(now, seconds as u64)
+            });
+        }
+        if cookie.secure && url.scheme != "https" {
+            return;
+        }
+        if cookie.same_site == SameSite::None && !cookie.secure {
+            return;
+        }
+
+        let existing = self.cookies.iter().position(|old| {
+            old.name == cookie.name && old.domain == cookie.domain && old.path == cookie.path
+        });
+        if let Some(i) = existing {
+            cookie.created = self.cookies[i].created;
+            self.cookies.remove(i);
+        }
+        if !cookie.is_expired(now) {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /**
+     The `Cookie` header value for a `method` request to `url`. `initiator` is the page or
+     redirect that led here; `None` means the user navigated directly, which counts as
+     same-site. Cross-site requests only carry `Lax` cookies when they are GET or HEAD.
+    */
+    pub fn header_for(
+        &mut self,
+        url: &URL,
+        method: Method,
+        initiator: Option<&URL>,
+    ) -> Option<String> {
+        let now = SystemTime::now();
+        self.cookies.retain(|cookie| !cookie.is_expired(now));
+        let same_site = initiator.is_none_or(|from| site(&from.host) == site(&url.host));
+        let safe = matches!(method, Method::Get | Method::Head);
+        let mut matching: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.matches(url))
+            .filter(|cookie| match cookie.same_site {
+                SameSite::Strict => same_site,
+                SameSite::Lax => same_site || safe,
+                SameSite::None => true,
+            })
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // Longer paths first, then older cookies first (section 5.4).
+        matching.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.created.cmp(&b.created))
+        });
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    /** Read persistent cookies written by `save`. A missing file is an empty jar. */
+    pub fn load(path: &Path) -> Self {
+        let text = fs::read_to_string(path).unwrap_or_default();
+        let cookies = text
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                let [domain, host_only, path, secure, expires, name, value, http_only, same_site] =
+                    fields.as_slice()
+                else {
+                    return None;
+                };
+                Some(Cookie {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    domain: domain.to_string(),
+                    host_only: *host_only == "TRUE",
+                    path: path.to_string(),
+                    expires: Some(date::after(UNIX_EPOCH, expires.parse().ok()?)),
+                    secure: *secure == "TRUE",
+                    http_only: *http_only == "TRUE",
+                    same_site: SameSite::parse(same_site)?,
+                    created: SystemTime::now(),
+                })
+            })
+            .collect();
+        Self { cookies }
+    }
+
+    /** Write every unexpired persistent cookie to `path`, one tab-separated line each. */
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let now = SystemTime::now();
+        let flag = |set: bool| if set { "TRUE" } else { "FALSE" };
+        let mut text = String::new();
+        for cookie in &self.cookies {
+            let Some(expires) = cookie.expires.filter(|_| !cookie.is_expired(now)) else {
+                continue;
+            };
+            let expires = expires.duration_since(UNIX_EPOCH).unwrap_or_default();
+            text += &format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                cookie.domain,
+                flag(cookie.host_only),
+                cookie.path,
+                flag(cookie.secure),
+                expires.as_secs(),
+                cookie.name,
+                cookie.value,
+                flag(cookie.http_only),
+                cookie.same_site.as_str()
+            );
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+}
+
+static JAR: Mutex<Option<CookieJar>> = Mutex::new(None);
+
+/** Run `f` on the shared jar, loading it from the configured cookie file on first use. */
+fn with_jar<T>(f: impl FnOnce(&mut CookieJar) -> T) -> T {
+    let mut jar = JAR.lock().unwrap();
+    let jar = jar.get_or_insert_with(|| match config::get().cookie_file {
+        Some(path) => CookieJar::load(&path),
+        None => CookieJar::default(),
+    });
+    f(jar)
+}
+
+/** Store every `Set-Cookie` value from a response to `url`, saving the jar if persistence is on. */
+pub fn store(url: &URL, set_cookies: &[String]) {
+    if set_cookies.is_empty() {
+        return;
+    }
+    with_jar(|jar| {
+        for set_cookie in set_cookies {
+            jar.store(url, set_cookie);
+        }
+        if let Some(path) = config::get().cookie_file {
+            if let Err(e) = jar.save(&path) {
+                eprintln!("Failed to save cookies to {}: {}", path.display(), e);
+            }
+        }
+    });
+}
+
+/** The `Cookie` header for a `method` request to `url`, if the jar has matching cookies. */
+pub fn header_for(url: &URL, method: Method, initiator: Option<&URL>) -> Option<String> {
+    with_jar(|jar| jar.header_for(url, method, initiator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domain_cannot_be_a_public_suffix() {
+        let url = URL::new("http://example.com/").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, "a=b; Domain=com");
+        let other = URL::new("http://other.com/").unwrap();
+        assert_eq!(jar.header_for(&other, Method::Get, None), None);
+        assert_eq!(jar.header_for(&url, Method::Get, None), None);
+
+        let url = URL::new("http://shop.example.co.uk/").unwrap();
+        jar.store(&url, "c=d; Domain=co.uk");
+        let other = URL::new("http://evil.co.uk/").unwrap();
+        assert_eq!(jar.header_for(&other, Method::Get, None), None);
+    }
+
+    #[test]
+    fn domain_equal_to_a_suffix_host_is_host_only() {
+        let url = URL::new("http://localhost/").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, "a=b; Domain=localhost");
+        assert_eq!(
+            jar.header_for(&url, Method::Get, None).as_deref(),
+            Some("a=b")
+        );
+        assert!(jar.cookies[0].host_only);
+    }
+
+    #[test]
+    fn domain_covers_subdomains() {
+        let url = URL::new("http://www.example.com/").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, "a=b; Domain=.example.com");
+        let other = URL::new("http://api.example.com/").unwrap();
+        assert_eq!(
+            jar.header_for(&other, Method::Get, None).as_deref(),
+            Some("a=b")
+        );
+    }
+
+    #[test]
+    fn site_uses_the_public_suffix_list() {
+        assert_eq!(site("www.example.com"), "example.com");
+        assert_eq!(site("a.b.example.co.uk"), "example.co.uk");
+        assert_eq!(site("user.github.io"), "user.github.io");
+        assert_eq!(site("example.com"), "example.com");
+        assert_eq!(site("localhost"), "localhost");
+        assert_eq!(site("192.168.0.1"), "192.168.0.1");
+    }
+
+    #[test]
+    fn same_site_depends_on_suffix_and_method() {
+        let url = URL::new("https://shop.example.co.uk/").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, "strict=1; SameSite=Strict");
+        jar.store(&url, "lax=2; SameSite=Lax");
+        jar.store(&url, "none=3; SameSite=None; Secure");
+
+        let same = URL::new("https://www.example.co.uk/").unwrap();
+        let header = jar.header_for(&url, Method::Post, Some(&same));
+        assert_eq!(header.as_deref(), Some("strict=1; lax=2; none=3"));
+
+        // Another name under co.uk is another site.
+        let cross = URL::new("https://evil.co.uk/").unwrap();
+        let header = jar.header_for(&url, Method::Get, Some(&cross));
+        assert_eq!(header.as_deref(), Some("lax=2; none=3"));
+        let header = jar.header_for(&url, Method::Head, Some(&cross));
+        assert_eq!(header.as_deref(), Some("lax=2; none=3"));
+        for method in [Method::Post, Method::Put, Method::Delete] {
+            let header = jar.header_for(&url, method, Some(&cross));
+            assert_eq!(header.as_deref(), Some("none=3"));
+        }
+
+        let header = jar.header_for(&url, Method::Post, None);
+        assert_eq!(header.as_deref(), Some("strict=1; lax=2; none=3"));
+    }
+
+    #[test]
+    fn huge_max_age_is_clamped() {
+        let url = URL::new("http://example.com/").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, "a=b; Max-Age=9223372036854775807");
+        assert_eq!(
+            jar.header_for(&url, Method::Get, None).as_deref(),
+            Some("a=b")
+        );
+    }
+
+    #[test]
+    fn huge_expiry_in_file_is_clamped() {
+        let path = std::env::temp_dir().join(format!("cookies-{}.txt", std::process::id()));
+        fs::write(
+            &path,
+            "example.com\tTRUE\t/\tFALSE\t18446744073709551615\ta\tb\tFALSE\tLax\n",
+        )
+        .unwrap();
+        let mut jar = CookieJar::load(&path);
+        fs::remove_file(&path).unwrap();
+        let url = URL::new("http://example.com/").unwrap();
+        assert_eq!(
+            jar.header_for(&url, Method::Get, None).as_deref(),
+            Some("a=b")
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::response::Response;
+use crate::networking::url::{percent_decode_bytes, URL};
+use base64::Engine;
+
+/** Media type assumed when a data URL does not name one (RFC 2397 section 2). */
+const DEFAULT_MEDIA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/** Decode a `data:[<mediatype>][;base64],<data>` URL into a response. */
+pub fn fetch(url: &URL) -> Result<Response> {
+    let (header, payload) = url
+        .path
+        .split_once(',')
+        .ok_or_else(|| NetworkError::InvalidUrl("data URL has no comma".to_owned()))?;
+
+    let mut params: Vec<&str> = header.split(';').map(|param| param.trim()).collect();
+    let base64 = params
+        .last()
+        .is_some_and(|param| param.eq_ignore_ascii_case("base64"));
+    if base64 {
+        params.pop();
+    }
+    let media_type = if params.first().is_some_and(|mime| mime.contains('/')) {
+        params.remove(0).to_lowercase()
+    } else {
+        String::new()
+    };
+    let params: Vec<&str> = params.into_iter().filter(|p| !p.is_empty()).collect();
+    let content_type = match (media_type.is_empty(), params.is_empty()) {
+        (true, true) => DEFAULT_MEDIA_TYPE.to_owned(),
+        (true, false) => format!("text/plain;{}", params.join(";")),
+        (false, true) => media_type,
+        (false, false) => format!("{};{}", media_type, params.join(";")),
+    };
+
+    let bytes = percent_decode_bytes(payload);
+    let body = if base64 {
+        let encoded: Vec<u8> = bytes
+            .into_iter()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect();
+        base64::engine::general_purpose::STANDARD
+            .decode(&encoded)
+            .or_else(|_| base64::engine::general_purpose::STANDARD_NO_PAD.decode(&encoded))
+            .map_err(|e| NetworkError::InvalidUrl(format!("bad base64 in data URL: {}", e)))?
+    } else {
+        bytes
+    };
+    Ok(Response::local(url, &content_type, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetched(url: &str) -> Result<Response> {
+        fetch(&URL::new(url)?)
+    }
+
+    /** The content type and body of a data URL that decodes. */
+    fn decoded(url: &str) -> (String, Vec<u8>) {
+        let response = fetched(url).unwrap();
+        let content_type = response.headers.get("content-type").unwrap().to_owned();
+        (content_type, response.body)
+    }
+
+    #[test]
+    fn missing_media_type_is_us_ascii_text() {
+        assert_eq!(
+            decoded("data:,hello"),
+            (DEFAULT_MEDIA_TYPE.to_owned(), b"hello".to_vec())
+        );
+        assert_eq!(decoded("data:;base64,aGk=").0, DEFAULT_MEDIA_TYPE);
+        assert_eq!(
+            decoded("data:;charset=utf-8,hi").0,
+            "text/plain;charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn media_type_and_parameters_are_kept() {
+        assert_eq!(decoded("data:Text/HTML,<p>hi</p>").0, "text/html");
+        assert_eq!(
+            decoded("data:text/html;charset=utf-8,x").0,
+            "text/html;charset=utf-8"
+        );
+        assert_eq!(decoded("data:image/png;base64,iVBORw==").0, "image/png");
+    }
+
+    #[test]
+    fn percent_encoded_bodies_are_decoded() {
+        assert_eq!(decoded("data:,Hello%2C%20World%21").1, b"Hello, World!");
+        assert_eq!(decoded("data:,caf%C3%A9").1, "café".as_bytes());
+        assert_eq!(decoded("data:,%FF%00").1, [0xFF, 0x00]);
+        assert_eq!(decoded("data:,a,b").1, b"a,b");
+        assert_eq!(decoded("data:text/plain,").1, b"");
+    }
+
+    #[test]
+    fn base64_bodies_are_decoded() {
+        assert_eq!(
+            decoded("data:;base64,SGVsbG8sIFdvcmxkIQ==").1,
+            b"Hello, World!"
+        );
+        assert_eq!(decoded("data:;BASE64,SGk").1, b"Hi");
+        assert_eq!(decoded("data:;base64,SGVs%20bG8%3D").1, b"Hello");
+        assert_eq!(decoded("data:;base64,/w==").1, [0xFF]);
+    }
+
+    #[test]
+    fn malformed_data_urls_are_errors() {
+        assert!(matches!(
+            fetched("data:text/plain"),
+            Err(NetworkError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            fetched("data:;base64,not*base64"),
+            Err(NetworkError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            fetched("data:;base64,SGk=="),
+            Err(NetworkError::InvalidUrl(_))
+        ));
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/** Days between 1970-01-01 and the given civil date (proleptic Gregorian calendar). */
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/** The civil date `(year, month, day)` that is `days` after 1970-01-01. */
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/** Leading ASCII digits of `token`, if there are between `min` and `max` of them. */
+fn digits(token: &str, min: usize, max: usize) -> Option<i64> {
+    let len = token.chars().take_while(|c| c.is_ascii_digit()).count();
+    if len < min || len > max {
+        return None;
+    }
+    token[..len].parse().ok()
+}
+
+/**
+ Parse a date the way RFC 6265 section 5.1.1 does. This accepts the IMF-fixdate,
+ RFC 850 and asctime forms used by HTTP as well as the looser forms seen in cookies.
+*/
+pub fn parse(input: &str) -> Option<SystemTime> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    let tokens = input
+        .split(|c: char| {
+            c == '\t'
+                || (' '..='/').contains(&c)
+                || (';'..='@').contains(&c)
+                || ('['..='`').contains(&c)
+                || ('{'..='~').contains(&c)
+        })
+        .filter(|token| !token.is_empty());
+    for token in tokens {
+        if time.is_none() {
+            let parts: Vec<&str> = token.splitn(3, ':').collect();
+            if parts.len() == 3 {
+                if let (Some(h), Some(m), Some(s)) = (
+                    digits(parts[0], 1, 2),
+                    digits(parts[1], 1, 2),
+                    digits(parts[2], 1, 2),
+                ) {
+                    time = Some((h, m, s));
+                    continue;
+                }
+            }
+        }
+        if day.is_none() {
+            if let Some(d) = digits(token, 1, 2) {
+                day = Some(d);
+                continue;
+            }
+        }
+        if month.is_none() {
+            let prefix = token.get(..3).unwrap_or_default().to_lowercase();
+            if let Some(m) = MONTHS.iter().position(|name| *name == prefix) {
+                month = Some(m as i64 + 1);
+                continue;
+            }
+        }
+        if year.is_none() {
+            if let Some(y) = digits(token, 2, 4) {
+                year = Some(y);
+                continue;
+            }
+        }
+    }
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    if (70..=99).contains(&year) {
+        year += 1900;
+    } else if (0..=69).contains(&year) {
+        year += 2000;
+    }
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    }
+}
+
+/** The latest time an expiry is kept as, the last second of the year 9999. */
+const LATEST: u64 = 253_402_300_799;
+
+/**
+ `seconds` after `time`, clamped to the end of the year 9999 so that a huge lifetime from a
+ server or a file cannot overflow.
+*/
+pub fn after(time: SystemTime, seconds: u64) -> SystemTime {
+    let latest = UNIX_EPOCH + Duration::from_secs(LATEST);
+    time.checked_add(Duration::from_secs(seconds))
+        .map_or(latest, |time| time.min(latest))
+}
+
+/** Days since the Unix epoch and seconds into that day, for times before it too. */
+fn days_and_seconds(time: SystemTime) -> (i64, i64) {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    (seconds.div_euclid(86400), seconds.rem_euclid(86400))
+}
+
+/** Format `time` as an HTTP IMF-fixdate, such as `Sun, 06 Nov 1994 08:49:37 GMT`. */
+pub fn format(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    let (days, second_of_day) = days_and_seconds(time);
+    let (year, month, day) = civil_from_days(days);
+    let month = MONTHS[month as usize - 1];
+    format!(
+        "{}, {:02} {}{} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        month[..1].to_uppercase(),
+        &month[1..],
+        year,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    )
+}
+
+/** Format `time` as an ISO 8601 UTC timestamp, such as `1994-11-06T08:49:37Z`. */
+pub fn format_iso(time: SystemTime) -> String {
+    let (days, second_of_day) = days_and_seconds(time);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    )
+}
@@ -0,0 +1,667 @@
+use crate::networking::charset;
+use crate::networking::config;
+use crate::networking::error::NetworkError;
+use crate::networking::response::{HeaderMap, Response};
+use crate::networking::url::{percent_decode, percent_decode_bytes, URL};
+use encoding_rs::Encoding;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/** How far a download has got. */
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    Downloading,
+    Complete,
+    /** Stopped by the user. What arrived is kept for resuming. */
+    Cancelled,
+    /** Cut off by an error, with what went wrong. What arrived is kept for resuming. */
+    Failed(String),
+}
+
+/** A file saved from a response instead of being shown, as listed on `about:downloads`. */
+#[derive(Debug, Clone)]
+pub struct Download {
+    /** The URL as it was opened, before redirects. Opening it again resumes the download. */
+    pub url: String,
+    /** Where the file ends up. Until it is complete it is written next to it, as `.part`. */
+    pub path: PathBuf,
+    pub received: u64,
+    /** The full size, when the server said. */
+    pub total: Option<u64>,
+    pub state: State,
+    /** The `ETag` or `Last-Modified` value that `If-Range` resumes against. */
+    validator: Option<String>,
+}
+
+impl Download {
+    /** Whether opening the URL again picks up where this download stopped. */
+    pub fn resumable(&self) -> bool {
+        self.state != State::Complete && self.validator.is_some() && self.received > 0
+    }
+}
+
+/** The file a download is written to until it is complete. */
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/** Where downloads are saved: the configured directory, the user's, or the working one. */
+fn directory() -> PathBuf {
+    config::get()
+        .downloads_dir
+        .or_else(dirs::download_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/** Where the list of downloads is kept between runs. */
+fn index_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("downloads.txt"))
+}
+
+/**
+ The disposition type and parameters of a `Content-Disposition` value (RFC 6266), with
+ quoted parameters unquoted and names lowercased.
+*/
+fn disposition(value: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => part.extend(chars.next()),
+            ';' if !quoted => parts.push(std::mem::take(&mut part)),
+            c => part.push(c),
+        }
+    }
+    parts.push(part);
+    let kind = parts[0].trim().to_lowercase();
+    let parameters = parts[1..]
+        .iter()
+        .filter_map(|parameter| parameter.split_once('='))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_owned()))
+        .collect();
+    (kind, parameters)
+}
+
+/** Decode an RFC 8187 extended value, such as `UTF-8''na%C3%AFve.txt`. */
+fn extended_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (parts.next()?, parts.next()?, parts.next()?);
+    let encoding = Encoding::for_label(charset.as_bytes())?;
+    let bytes = percent_decode_bytes(encoded);
+    let (decoded, _, _) = encoding.decode(&bytes);
+    Some(decoded.into_owned())
+}
+
+/**
+ A file name that is safe to create in the downloads directory: no directories, no
+ characters file systems reject, and no leading dot.
+*/
+fn sanitize(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !"<>:\"|?*".contains(*c))
+        .collect();
+    let name = name.trim().trim_start_matches('.').trim();
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+/** The name to save a response from `url` under, preferring the one the server suggests. */
+fn file_name(url: &URL, headers: &HeaderMap) -> String {
+    let (_, parameters) = disposition(headers.get("content-disposition").unwrap_or_default());
+    let parameter = |name: &str| {
+        parameters
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    parameter("filename*")
+        .and_then(extended_value)
+        .or_else(|| parameter("filename").map(str::to_owned))
+        .and_then(|name| sanitize(&name))
+        .or_else(|| {
+            sanitize(&percent_decode(
+                url.path.rsplit('/').next().unwrap_or_default(),
+            ))
+        })
+        .unwrap_or_else(|| "download".to_owned())
+}
+
+/** `name` in `dir`, numbered like `report (1).pdf` if a file or download already has it. */
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    let mut path = dir.join(name);
+    let mut n = 1;
+    while path.exists() || part_path(&path).exists() {
+        path = dir.join(format!("{} ({}){}", stem, n, extension));
+        n += 1;
+    }
+    path
+}
+
+/** Where a `206` response starts and the full size, from `Content-Range: bytes 10-99/100`. */
+fn content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (span, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = span.split_once('-')?;
+    Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
+/** Read the list written by `save_index`. A missing file is an empty list. */
+fn load_index(path: &Path) -> Vec<Download> {
+    let text = fs::read_to_string(path).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [url, path, received, total, state, validator, reason] = fields.as_slice() else {
+                return None;
+            };
+            let state = match *state {
+                "complete" => State::Complete,
+                "cancelled" => State::Cancelled,
+                "failed" => State::Failed(reason.to_string()),
+                // Still downloading when the browser quit.
+                _ => State::Failed("interrupted".to_owned()),
+            };
+            Some(Download {
+                url: url.to_string(),
+                path: PathBuf::from(path),
+                received: received.parse().ok()?,
+                total: total.parse().ok(),
+                state,
+                validator: (!validator.is_empty()).then(|| validator.to_string()),
+            })
+        })
+        .collect()
+}
+
+/** Write every download to `path`, one tab-separated line each. */
+fn save_index(downloads: &[Download], path: &Path) -> std::io::Result<()> {
+    let mut text = String::new();
+    for download in downloads {
+        let (state, reason) = match &download.state {
+            State::Downloading => ("downloading", ""),
+            State::Complete => ("complete", ""),
+            State::Cancelled => ("cancelled", ""),
+            State::Failed(reason) => ("failed", reason.as_str()),
+        };
+        text += &format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            download.url,
+            download.path.display(),
+            download.received,
+            download
+                .total
+                .map(|total| total.to_string())
+                .unwrap_or_default(),
+            state,
+            download.validator.as_deref().unwrap_or_default(),
+            reason.replace(|c: char| c.is_control(), " ")
+        );
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+static DOWNLOADS: Mutex<Option<Vec<Download>>> = Mutex::new(None);
+
+/** Run `f` on the shared list, loading it from the index file on first use. */
+fn with_downloads<T>(f: impl FnOnce(&mut Vec<Download>) -> T) -> T {
+    let mut downloads = DOWNLOADS.lock().unwrap();
+    let downloads = downloads.get_or_insert_with(|| {
+        index_path()
+            .map(|path| load_index(&path))
+            .unwrap_or_default()
+    });
+    f(downloads)
+}
+
+/** Save the list of downloads, if there is somewhere to keep it. */
+fn persist(downloads: &[Download]) {
+    if let Some(path) = index_path() {
+        if let Err(e) = save_index(downloads, &path) {
+            eprintln!("Failed to save downloads to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/** Every download, oldest first. */
+pub fn list() -> Vec<Download> {
+    with_downloads(|downloads| downloads.clone())
+}
+
+/**
+ Whether a response with `status` and `headers` should be saved rather than shown: a
+ successful one that is an attachment, or of a type the browser cannot display.
+*/
+pub fn wanted(status: u16, headers: &HeaderMap) -> bool {
+    if !matches!(status, 200 | 206) {
+        return false;
+    }
+    let attachment = headers
+        .get("content-disposition")
+        .is_some_and(|value| disposition(value).0 == "attachment");
+    attachment || !charset::is_text(headers.get("content-type"))
+}
+
+/**
+ Extra headers for a GET of `url` that resume its last download, if that stopped partway.
+ `If-Range` makes the server send the whole file instead if it has changed since.
+*/
+pub fn resume_headers(url: &str) -> Vec<(String, String)> {
+    with_downloads(|downloads| resume(downloads, url))
+}
+
+/** `resume_headers` for the last of `downloads` opened as `url`. */
+fn resume(downloads: &[Download], url: &str) -> Vec<(String, String)> {
+    let Some(download) = downloads.iter().rev().find(|download| download.url == url) else {
+        return Vec::new();
+    };
+    let (Some(validator), true) = (&download.validator, download.resumable()) else {
+        return Vec::new();
+    };
+    let size = fs::metadata(part_path(&download.path)).map_or(0, |part| part.len());
+    if size == 0 {
+        return Vec::new();
+    }
+    vec![
+        ("Range".to_owned(), format!("bytes={}-", size)),
+        ("If-Range".to_owned(), validator.clone()),
+        // Ranges count bytes of the encoded body, so ask for it unencoded.
+        ("Accept-Encoding".to_owned(), "identity".to_owned()),
+    ]
+}
+
+/** A download whose body is being written to disk. */
+pub struct Saving {
+    index: usize,
+    file: Option<File>,
+    /** Why the body cannot be saved, once writing has failed. */
+    error: Option<String>,
+}
+
+/**
+ Start saving the response from `url` with `status` and `headers`, opened as `requested`.
+ A `206` continues the last download of `requested` and a `200` starts it over; otherwise a
+ new download is added.
+*/
+pub fn begin(requested: &str, url: &URL, status: u16, headers: &HeaderMap) -> Saving {
+    with_downloads(|downloads| {
+        let dir = directory();
+        let (index, opened) = open(downloads, &dir, requested, url, status, headers);
+        downloads[index].state = State::Downloading;
+        persist(downloads);
+        match opened {
+            Ok(file) => Saving {
+                index,
+                file: Some(file),
+                error: None,
+            },
+            Err(error) => Saving {
+                index,
+                file: None,
+                error: Some(error),
+            },
+        }
+    })
+}
+
+/**
+ The entry of `downloads` that a response for `begin` goes to, adding one saved in `dir` if
+ needed, and the `.part` file to write its body to or why there is none.
+*/
+fn open(
+    downloads: &mut Vec<Download>,
+    dir: &Path,
+    requested: &str,
+    url: &URL,
+    status: u16,
+    headers: &HeaderMap,
+) -> (usize, Result<File, String>) {
+    let etag = headers.get("etag").filter(|etag| !etag.starts_with("W/"));
+    let validator = etag.or(headers.get("last-modified")).map(str::to_owned);
+    let encoded = headers
+        .get("content-encoding")
+        .is_some_and(|encoding| !encoding.eq_ignore_ascii_case("identity"));
+    // Content-Length counts encoded bytes, which are not what is saved.
+    let length = headers
+        .get("content-length")
+        .filter(|_| !encoded)
+        .and_then(|length| length.parse().ok());
+    let last = downloads
+        .iter()
+        .rposition(|download| download.url == requested)
+        .filter(|&i| downloads[i].state != State::Complete);
+    let range = headers.get("content-range").and_then(content_range);
+    match (status, last, range) {
+        (206, Some(i), Some((start, total))) => {
+            let part = part_path(&downloads[i].path);
+            let size = fs::metadata(&part).map_or(0, |part| part.len());
+            let download = &mut downloads[i];
+            download.received = start;
+            download.total = total;
+            let opened = if size == start {
+                OpenOptions::new()
+                    .append(true)
+                    .open(&part)
+                    .map_err(|e| e.to_string())
+            } else {
+                Err(format!("resumed at byte {} of a {} byte file", start, size))
+            };
+            (i, opened)
+        }
+        (206, ..) => {
+            let path = unique_path(dir, &file_name(url, headers));
+            downloads.push(Download {
+                url: requested.to_owned(),
+                path,
+                received: 0,
+                total: None,
+                state: State::Downloading,
+                validator: None,
+            });
+            let error = "the server sent part of a file that was not asked for";
+            (downloads.len() - 1, Err(error.to_owned()))
+        }
+        (_, last, _) => {
+            let index = match last {
+                Some(i) => i,
+                None => {
+                    let path = unique_path(dir, &file_name(url, headers));
+                    downloads.push(Download {
+                        url: requested.to_owned(),
+                        path,
+                        received: 0,
+                        total: None,
+                        state: State::Downloading,
+                        validator: None,
+                    });
+                    downloads.len() - 1
+                }
+            };
+            let download = &mut downloads[index];
+            download.received = 0;
+            download.total = length;
+            download.validator = validator;
+            let part = part_path(&download.path);
+            let opened = download
+                .path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| File::create(&part))
+                .map_err(|e| e.to_string());
+            (index, opened)
+        }
+    }
+}
+
+impl Saving {
+    /** Append the next piece of the body. A failure is kept for `finish` to report. */
+    pub fn write(&mut self, data: &[u8]) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        if let Err(e) = file.write_all(data) {
+            self.error = Some(e.to_string());
+            self.file = None;
+            return;
+        }
+        with_downloads(|downloads| downloads[self.index].received += data.len() as u64);
+    }
+
+    /**
+     Close the download once its response is over, or failed with `error`. A complete file
+     is moved from `.part` to its own name; anything else is left to be resumed.
+    */
+    pub fn finish(mut self, error: Option<&NetworkError>) {
+        // Closed before it is renamed.
+        self.file = None;
+        let failure = self.error.take();
+        with_downloads(|downloads| {
+            let download = &mut downloads[self.index];
+            download.state = match (failure, error) {
+                (Some(failure), _) => State::Failed(failure),
+                (None, Some(NetworkError::Cancelled)) => State::Cancelled,
+                (None, Some(error)) => State::Failed(error.to_string()),
+                (None, None) => match fs::rename(part_path(&download.path), &download.path) {
+                    Ok(()) => {
+                        download.total = Some(download.received);
+                        State::Complete
+                    }
+                    Err(e) => State::Failed(e.to_string()),
+                },
+            };
+            persist(downloads);
+        });
+    }
+}
+
+impl Drop for Saving {
+    /** A download dropped before `finish`, because its response was retried, is cut off. */
+    fn drop(&mut self) {
+        with_downloads(|downloads| {
+            let download = &mut downloads[self.index];
+            if download.state == State::Downloading {
+                download.state = State::Failed("interrupted".to_owned());
+                persist(downloads);
+            }
+        });
+    }
+}
+
+/** Save a `response` opened as `requested` whose body is already in memory, such as a cached one. */
+pub fn save(requested: &str, response: &Response) {
+    let mut saving = begin(requested, &response.url, response.status, &response.headers);
+    saving.write(&response.body);
+    saving.finish(None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(fields: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (key, value) in fields {
+            headers.insert(key, value);
+        }
+        headers
+    }
+
+    /** An empty directory of its own for a test named `name`. */
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("download-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_disposition_parameters() {
+        let (kind, parameters) = disposition(r#"Attachment; FileName="a;b \"c\".pdf"; size=3"#);
+        assert_eq!(kind, "attachment");
+        assert_eq!(
+            parameters,
+            [
+                ("filename".to_owned(), r#"a;b "c".pdf"#.to_owned()),
+                ("size".to_owned(), "3".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn extended_file_name_wins() {
+        let url = URL::new("http://example.com/get?id=1").unwrap();
+        let utf8 = headers(&[(
+            "Content-Disposition",
+            "attachment; filename=\"fallback.txt\"; filename*=UTF-8''na%C3%AFve%20file.txt",
+        )]);
+        assert_eq!(file_name(&url, &utf8), "naïve file.txt");
+        let latin1 = headers(&[(
+            "Content-Disposition",
+            "attachment; filename*=iso-8859-1'en'%A3%20rates.txt",
+        )]);
+        assert_eq!(file_name(&url, &latin1), "£ rates.txt");
+        // An unknown charset falls back to the plain parameter.
+        let unknown = headers(&[(
+            "Content-Disposition",
+            "attachment; filename=plain.txt; filename*=x-bogus''weird.txt",
+        )]);
+        assert_eq!(file_name(&url, &unknown), "plain.txt");
+    }
+
+    #[test]
+    fn file_name_falls_back_to_the_url() {
+        let url = URL::new("http://example.com/files/report%202024.pdf").unwrap();
+        assert_eq!(file_name(&url, &HeaderMap::new()), "report 2024.pdf");
+        let url = URL::new("http://example.com/").unwrap();
+        assert_eq!(file_name(&url, &HeaderMap::new()), "download");
+    }
+
+    #[test]
+    fn file_names_cannot_leave_the_directory() {
+        assert_eq!(sanitize("../../etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(
+            sanitize(r"..\..\Windows\win.ini").as_deref(),
+            Some("win.ini")
+        );
+        assert_eq!(sanitize("/etc/").as_deref(), None);
+        assert_eq!(sanitize("..").as_deref(), None);
+        assert_eq!(sanitize(".bashrc").as_deref(), Some("bashrc"));
+        assert_eq!(sanitize("a<b>:c|d?*\u{7}.txt").as_deref(), Some("abcd.txt"));
+        let url = URL::new("http://example.com/").unwrap();
+        let traversal = headers(&[(
+            "Content-Disposition",
+            "attachment; filename=\"../../.ssh/authorized_keys\"",
+        )]);
+        assert_eq!(file_name(&url, &traversal), "authorized_keys");
+        let encoded = headers(&[(
+            "Content-Disposition",
+            "attachment; filename*=UTF-8''..%2F..%2F.profile",
+        )]);
+        assert_eq!(file_name(&url, &encoded), "profile");
+    }
+
+    #[test]
+    fn taken_names_are_numbered() {
+        let dir = scratch_dir("unique");
+        fs::write(dir.join("report.pdf"), "").unwrap();
+        fs::write(dir.join("report (1).pdf.part"), "").unwrap();
+        assert_eq!(unique_path(&dir, "report.pdf"), dir.join("report (2).pdf"));
+        assert_eq!(unique_path(&dir, "notes"), dir.join("notes"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_content_range() {
+        assert_eq!(content_range("bytes 10-99/100"), Some((10, Some(100))));
+        assert_eq!(content_range("bytes 10-99/*"), Some((10, None)));
+        for malformed in [
+            "",
+            "bytes=10-99/100",
+            "bytes 10/100",
+            "items 10-99/100",
+            "bytes x-99/100",
+            "bytes -10-99/100",
+            "bytes */100",
+        ] {
+            assert_eq!(content_range(malformed), None, "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn resumes_with_a_matching_range() {
+        let dir = scratch_dir("resume");
+        let url = URL::new("http://example.com/big.bin").unwrap();
+        let requested = url.to_string();
+        let mut downloads = Vec::new();
+        assert!(resume(&downloads, &requested).is_empty());
+
+        let first = headers(&[("ETag", "\"v1\""), ("Content-Length", "10")]);
+        let (index, file) = open(&mut downloads, &dir, &requested, &url, 200, &first);
+        file.unwrap().write_all(b"hello").unwrap();
+        downloads[index].received = 5;
+        downloads[index].state = State::Cancelled;
+        assert_eq!(
+            resume(&downloads, &requested),
+            [
+                ("Range".to_owned(), "bytes=5-".to_owned()),
+                ("If-Range".to_owned(), "\"v1\"".to_owned()),
+                ("Accept-Encoding".to_owned(), "identity".to_owned()),
+            ]
+        );
+
+        let rest = headers(&[("ETag", "\"v1\""), ("Content-Range", "bytes 5-9/10")]);
+        let (resumed, file) = open(&mut downloads, &dir, &requested, &url, 206, &rest);
+        assert_eq!(resumed, index);
+        file.unwrap().write_all(b"world").unwrap();
+        let part = part_path(&downloads[index].path);
+        assert_eq!(fs::read(&part).unwrap(), b"helloworld");
+        assert_eq!(downloads[index].total, Some(10));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn whole_file_instead_of_a_range_starts_over() {
+        let dir = scratch_dir("restart");
+        let url = URL::new("http://example.com/big.bin").unwrap();
+        let requested = url.to_string();
+        let mut downloads = Vec::new();
+        let first = headers(&[("ETag", "\"v1\"")]);
+        let (index, file) = open(&mut downloads, &dir, &requested, &url, 200, &first);
+        file.unwrap().write_all(b"stale").unwrap();
+        downloads[index].received = 5;
+        downloads[index].state = State::Failed("interrupted".to_owned());
+
+        // The file changed, so If-Range made the server send all of it.
+        let changed = headers(&[("ETag", "\"v2\""), ("Content-Length", "3")]);
+        let (restarted, file) = open(&mut downloads, &dir, &requested, &url, 200, &changed);
+        assert_eq!(restarted, index);
+        assert_eq!(downloads.len(), 1);
+        let part = part_path(&downloads[index].path);
+        assert_eq!(fs::read(&part).unwrap(), b"");
+        file.unwrap().write_all(b"new").unwrap();
+        assert_eq!(fs::read(&part).unwrap(), b"new");
+        assert_eq!(downloads[index].received, 0);
+        assert_eq!(downloads[index].total, Some(3));
+        assert_eq!(downloads[index].validator.as_deref(), Some("\"v2\""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_ranges_are_not_appended() {
+        let dir = scratch_dir("bad-range");
+        let url = URL::new("http://example.com/big.bin").unwrap();
+        let requested = url.to_string();
+        let mut downloads = Vec::new();
+        let first = headers(&[("ETag", "\"v1\"")]);
+        let (index, file) = open(&mut downloads, &dir, &requested, &url, 200, &first);
+        file.unwrap().write_all(b"hello").unwrap();
+        downloads[index].state = State::Cancelled;
+
+        // A range that does not start where the file stops.
+        let gap = headers(&[("Content-Range", "bytes 7-9/10")]);
+        let (same, file) = open(&mut downloads, &dir, &requested, &url, 206, &gap);
+        assert_eq!(same, index);
+        assert!(file.is_err());
+        // A range that cannot be read at all is not taken for the rest of the file.
+        let malformed = headers(&[("Content-Range", "bytes=5-9/10")]);
+        let (other, file) = open(&mut downloads, &dir, &requested, &url, 206, &malformed);
+        assert_ne!(other, index);
+        assert!(file.is_err());
+        let part = part_path(&downloads[index].path);
+        assert_eq!(fs::read(&part).unwrap(), b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
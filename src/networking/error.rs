@@ -0,0 +1,111 @@
+use std::{error, fmt, io};
+
+/** Everything that can go wrong while parsing a URL or fetching it. */
+#[derive(Debug)]
+pub enum NetworkError {
+    /** DNS lookup or TCP connect failed. */
+    Connect(io::Error),
+    /** TLS setup or handshake failed. */
+    Tls(String),
+    /** A local file or directory could not be read. */
+    File(io::Error),
+    /** Reading from or writing to an established connection failed. */
+    Io(io::Error),
+    /** Connecting, waiting for data or the whole request took longer than allowed. */
+    Timeout(String),
+    MalformedStatusLine(String),
+    MalformedHeader(String),
+    UnsupportedEncoding(String),
+    /** A compressed body could not be decoded. */
+    Decompress(String),
+    /** A chunked body did not follow the chunk framing. */
+    MalformedChunk(String),
+    InvalidUrl(String),
+    /** A proxy refused the connection or did not speak its protocol. */
+    Proxy(String),
+    /** A session archive could not be read or written, or lacks a requested page. */
+    Archive(String),
+    /** The user stopped the load. */
+    Cancelled,
+    /** A redirect pointed back at a URL already visited. */
+    RedirectLoop(String),
+    /** More redirects than the configured limit. */
+    TooManyRedirects(usize),
+    /** A page from the network redirected to a local URL, such as a `file:` one. */
+    ForbiddenRedirect(String),
+}
+
+pub type Result<T> = std::result::Result<T, NetworkError>;
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Connect(e) => write!(f, "could not connect: {}", e),
+            NetworkError::Tls(e) => write!(f, "secure connection failed: {}", e),
+            NetworkError::File(e) => write!(f, "could not read file: {}", e),
+            NetworkError::Io(e) => write!(f, "connection error: {}", e),
+            NetworkError::Timeout(what) => write!(f, "timed out: {}", what),
+            NetworkError::MalformedStatusLine(line) => {
+                write!(f, "malformed status line: {:?}", line)
+            }
+            NetworkError::MalformedHeader(line) => write!(f, "malformed header: {:?}", line),
+            NetworkError::UnsupportedEncoding(encoding) => {
+                write!(f, "unsupported encoding: {}", encoding)
+            }
+            NetworkError::Decompress(reason) => write!(f, "could not decompress body: {}", reason),
+            NetworkError::MalformedChunk(reason) => write!(f, "malformed chunk: {}", reason),
+            NetworkError::InvalidUrl(reason) => write!(f, "invalid URL: {}", reason),
+            NetworkError::Proxy(reason) => write!(f, "proxy error: {}", reason),
+            NetworkError::Archive(reason) => write!(f, "archive: {}", reason),
+            NetworkError::Cancelled => write!(f, "cancelled"),
+            NetworkError::RedirectLoop(url) => write!(f, "redirect loop at {}", url),
+            NetworkError::TooManyRedirects(max) => {
+                write!(f, "too many redirects (limit is {})", max)
+            }
+            NetworkError::ForbiddenRedirect(url) => write!(f, "refused redirect to {}", url),
+        }
+    }
+}
+
+impl error::Error for NetworkError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            NetworkError::Connect(e) | NetworkError::File(e) | NetworkError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NetworkError {
+    fn from(e: io::Error) -> Self {
+        // Errors raised inside a reader, such as a cancelled load, come back out unchanged.
+        let e = match e.downcast::<NetworkError>() {
+            Ok(inner) => return inner,
+            Err(e) => e,
+        };
+        match e.kind() {
+            // Sockets with a read timeout report it as either kind, depending on the platform.
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                NetworkError::Timeout(e.to_string())
+            }
+            _ => NetworkError::Io(e),
+        }
+    }
+}
+
+impl From<native_tls::Error> for NetworkError {
+    fn from(e: native_tls::Error) -> Self {
+        NetworkError::Tls(e.to_string())
+    }
+}
+
+impl<S> From<native_tls::HandshakeError<S>> for NetworkError {
+    fn from(e: native_tls::HandshakeError<S>) -> Self {
+        match e {
+            native_tls::HandshakeError::Failure(e) => NetworkError::Tls(e.to_string()),
+            native_tls::HandshakeError::WouldBlock(_) => {
+                NetworkError::Timeout("TLS handshake".to_owned())
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::response::Response;
+use crate::networking::url::{percent_decode, percent_encode, PATH_ENCODE_SET, URL};
+use std::fs;
+use std::path::Path;
+
+/** Guess a MIME type from a file extension. */
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "txt" | "md" | "rs" | "toml" => "text/plain",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/** Read a `file://` URL from disk, listing its entries if it names a directory. */
+pub fn fetch(url: &URL) -> Result<Response> {
+    let path = percent_decode(&url.path);
+    let path = Path::new(&path);
+    if path.is_dir() {
+        let listing = directory_listing(url, path)?;
+        return Ok(Response::local(url, "text/html", listing.into_bytes()));
+    }
+    let body = fs::read(path).map_err(NetworkError::File)?;
+    Ok(Response::local(url, mime_type(path), body))
+}
+
+/** Generate an HTML index of the entries in `dir`. */
+fn directory_listing(url: &URL, dir: &Path) -> Result<String> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(NetworkError::File)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            (name, is_dir)
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let title = html_escape::encode_text(&dir.to_string_lossy()).into_owned();
+    let mut html = format!(
+        "<html><head><title>Index of {0}</title></head><body><h1>Index of {0}</h1>",
+        title
+    );
+    let base = url.path.trim_end_matches('/');
+    if dir.parent().is_some() {
+        html += &format!("<p><a href=\"file://{}/..\">..</a></p>", base);
+    }
+    for (name, is_dir) in entries {
+        let slash = if is_dir { "/" } else { "" };
+        html += &format!(
+            "<p><a href=\"file://{}/{}{}\">{}{}</a></p>",
+            base,
+            percent_encode(&name, PATH_ENCODE_SET),
+            slash,
+            html_escape::encode_text(&name),
+            slash
+        );
+    }
+    html += "</body></html>";
+    Ok(html)
+}
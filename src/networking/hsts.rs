@@ -0,0 +1,360 @@
+use crate::networking::config;
+use crate::networking::date;
+use crate::networking::response::Response;
+use crate::networking::url::URL;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ Hosts that are HTTPS-only before they are ever visited, with whether their subdomains are
+ too. A small sample of the list browsers ship, starting with the TLDs that are preloaded
+ whole.
+*/
+const PRELOAD: [(&str, bool); 6] = [
+    ("app", true),
+    ("dev", true),
+    ("page", true),
+    ("new", true),
+    ("github.com", true),
+    ("paypal.com", false),
+];
+
+/** The longest a policy is kept, a year, however long the server asks for (section 8.1.1). */
+const MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+/** A host that asked to be reached over HTTPS only (RFC 6797 section 5.1). */
+#[derive(Debug, Clone)]
+struct Policy {
+    host: String,
+    include_subdomains: bool,
+    expires: SystemTime,
+}
+
+/** The policy a `Strict-Transport-Security` value sets: its max-age and includeSubDomains. */
+fn parse(value: &str) -> Option<(u64, bool)> {
+    let mut max_age = None;
+    let mut include_subdomains = false;
+    let mut seen = Vec::new();
+    for directive in value.split(';') {
+        let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+        // A directive given twice makes the whole header invalid (section 6.1).
+        if seen.contains(&name) {
+            return None;
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        match name.as_str() {
+            "max-age" => max_age = Some(value.parse().ok()?),
+            "includesubdomains" => include_subdomains = true,
+            _ => {}
+        }
+        seen.push(name);
+    }
+    Some((max_age?, include_subdomains))
+}
+
+/** Whether `host` is `domain` or one of its subdomains. */
+fn is_subdomain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/** Known HSTS hosts, noted from responses or loaded from the HSTS file. */
+#[derive(Debug, Default)]
+pub struct HstsStore {
+    policies: Vec<Policy>,
+}
+
+impl HstsStore {
+    /**
+     Apply a `Strict-Transport-Security` value received over a verified connection to
+     `host` (section 8.1). Returns whether the store changed.
+    */
+    pub fn store(&mut self, host: &str, value: &str) -> bool {
+        // IP addresses cannot be HSTS hosts (section 8.1.1).
+        if host.starts_with('[') || host.parse::<std::net::IpAddr>().is_ok() {
+            return false;
+        }
+        let Some((max_age, include_subdomains)) = parse(value) else {
+            return false;
+        };
+        self.policies.retain(|policy| policy.host != host);
+        // max-age=0 means "forget this host".
+        if max_age > 0 {
+            self.policies.push(Policy {
+                host: host.to_owned(),
+                include_subdomains,
+                expires: date::after(SystemTime::now(), max_age.min(MAX_AGE)),
+            });
+        }
+        true
+    }
+
+    /** Whether `host` must only be reached over HTTPS (section 8.2). */
+    pub fn is_known(&mut self, host: &str, preload: bool) -> bool {
+        let now = SystemTime::now();
+        self.policies.retain(|policy| policy.expires > now);
+        let covers = |domain: &str, include_subdomains: bool| {
+            host == domain || (include_subdomains && is_subdomain(host, domain))
+        };
+        self.policies
+            .iter()
+            .any(|policy| covers(&policy.host, policy.include_subdomains))
+            || (preload
+                && PRELOAD
+                    .iter()
+                    .any(|&(domain, include_subdomains)| covers(domain, include_subdomains)))
+    }
+
+    /**
+     Note the `Strict-Transport-Security` header of a `response` to `url`, if it came over
+     HTTPS with a verified certificate. Returns whether the store changed.
+    */
+    fn note(&mut self, url: &URL, response: &Response) -> bool {
+        let verified = response
+            .certificate
+            .as_ref()
+            .is_some_and(|certificate| certificate.verified);
+        if url.scheme != "https" || !verified {
+            return false;
+        }
+        // Only the first header counts when a response has several (section 8.1).
+        match response.headers.get("strict-transport-security") {
+            Some(value) => self.store(&url.host, value),
+            None => false,
+        }
+    }
+
+    /** `upgrade` against this store, counting the preload list when `preload` is set. */
+    fn upgrade(&mut self, url: &URL, preload: bool) -> Option<URL> {
+        if url.scheme != "http" || !self.is_known(&url.host, preload) {
+            return None;
+        }
+        let mut upgraded = url.clone();
+        upgraded.scheme = "https".to_owned();
+        if upgraded.port == 80 {
+            upgraded.port = 443;
+        }
+        Some(upgraded)
+    }
+
+    /** Read the policies written by `save`. A missing file is an empty store. */
+    pub fn load(path: &Path) -> Self {
+        let text = fs::read_to_string(path).unwrap_or_default();
+        let policies = text
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                let [host, include_subdomains, expires] = fields.as_slice() else {
+                    return None;
+                };
+                Some(Policy {
+                    host: host.to_string(),
+                    include_subdomains: *include_subdomains == "TRUE",
+                    expires: date::after(UNIX_EPOCH, expires.parse().ok()?),
+                })
+            })
+            .collect();
+        Self { policies }
+    }
+
+    /** Write every unexpired policy to `path`, one tab-separated line each. */
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let now = SystemTime::now();
+        let mut text = String::new();
+        for policy in self.policies.iter().filter(|policy| policy.expires > now) {
+            let expires = policy
+                .expires
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            text += &format!(
+                "{}\t{}\t{}\n",
+                policy.host,
+                if policy.include_subdomains {
+                    "TRUE"
+                } else {
+                    "FALSE"
+                },
+                expires.as_secs()
+            );
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+}
+
+static STORE: Mutex<Option<HstsStore>> = Mutex::new(None);
+
+/** Run `f` on the shared store, loading it from the configured HSTS file on first use. */
+fn with_store<T>(f: impl FnOnce(&mut HstsStore) -> T) -> T {
+    let mut store = STORE.lock().unwrap();
+    let store = store.get_or_insert_with(|| match config::get().hsts_file {
+        Some(path) => HstsStore::load(&path),
+        None => HstsStore::default(),
+    });
+    f(store)
+}
+
+/**
+ Note the `Strict-Transport-Security` header of a response to `url`, saving the store if
+ persistence is on. Only responses over HTTPS with a verified certificate count.
+*/
+pub fn store(url: &URL, response: &Response) {
+    with_store(|store| {
+        if !store.note(url, response) {
+            return;
+        }
+        if let Some(path) = config::get().hsts_file {
+            if let Err(e) = store.save(&path) {
+                eprintln!("Failed to save HSTS hosts to {}: {}", path.display(), e);
+            }
+        }
+    });
+}
+
+/**
+ `url` rewritten to `https:` if its host is a known HSTS host, or `None` if it can go as it
+ is. An explicit port 80 becomes 443, and any other port is kept (section 8.3).
+*/
+pub fn upgrade(url: &URL) -> Option<URL> {
+    let preload = config::get().hsts_preload;
+    with_store(|store| store.upgrade(url, preload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::tls::Certificate;
+
+    #[test]
+    fn huge_max_age_is_capped() {
+        let mut store = HstsStore::default();
+        assert!(store.store("example.com", "max-age=18446744073709551615"));
+        assert!(store.is_known("example.com", false));
+        let expires = store.policies[0].expires;
+        assert!(expires <= date::after(SystemTime::now(), MAX_AGE));
+    }
+
+    #[test]
+    fn huge_expiry_in_file_is_clamped() {
+        let path = std::env::temp_dir().join(format!("hsts-{}.txt", std::process::id()));
+        fs::write(&path, "example.com\tTRUE\t18446744073709551615\n").unwrap();
+        let mut store = HstsStore::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(store.is_known("www.example.com", false));
+    }
+
+    fn url(url: &str) -> URL {
+        URL::new(url).unwrap()
+    }
+
+    /** A response to `url` with `header`, over a certificate that is `verified` or not. */
+    fn response(url: &URL, header: &str, verified: bool) -> Response {
+        let mut response = Response::local(url, "text/html", Vec::new());
+        response.headers.insert("Strict-Transport-Security", header);
+        response.certificate = Some(Certificate {
+            subject: "CN=example.com".to_owned(),
+            issuer: "CN=Test CA".to_owned(),
+            not_before: UNIX_EPOCH,
+            not_after: SystemTime::now(),
+            fingerprint: String::new(),
+            verified,
+        });
+        response
+    }
+
+    fn upgraded(store: &mut HstsStore, from: &str) -> Option<String> {
+        store.upgrade(&url(from), false).map(|url| url.to_string())
+    }
+
+    #[test]
+    fn upgrades_known_hosts_to_https() {
+        let mut store = HstsStore::default();
+        let secure = url("https://example.com/");
+        assert!(store.note(&secure, &response(&secure, "max-age=60", true)));
+
+        assert_eq!(
+            upgraded(&mut store, "http://example.com/a?b").as_deref(),
+            Some("https://example.com/a?b")
+        );
+        assert_eq!(
+            upgraded(&mut store, "http://example.com:80/").as_deref(),
+            Some("https://example.com/")
+        );
+        let kept = store
+            .upgrade(&url("http://example.com:8080/"), false)
+            .unwrap();
+        assert_eq!((kept.scheme.as_str(), kept.port), ("https", 8080));
+        assert_eq!(upgraded(&mut store, "http://other.com/"), None);
+        assert_eq!(upgraded(&mut store, "http://www.example.com/"), None);
+        assert_eq!(upgraded(&mut store, "https://example.com/"), None);
+    }
+
+    #[test]
+    fn include_subdomains_covers_subdomains() {
+        let mut store = HstsStore::default();
+        let secure = url("https://example.com/");
+        store.note(
+            &secure,
+            &response(&secure, "max-age=60; includeSubDomains", true),
+        );
+        assert_eq!(
+            upgraded(&mut store, "http://a.b.example.com/").as_deref(),
+            Some("https://a.b.example.com/")
+        );
+        assert_eq!(upgraded(&mut store, "http://notexample.com/"), None);
+    }
+
+    #[test]
+    fn max_age_zero_removes_the_host() {
+        let mut store = HstsStore::default();
+        let secure = url("https://example.com/");
+        store.note(&secure, &response(&secure, "max-age=60", true));
+        assert!(store.note(&secure, &response(&secure, "max-age=0", true)));
+        assert!(store.policies.is_empty());
+        assert_eq!(upgraded(&mut store, "http://example.com/"), None);
+    }
+
+    #[test]
+    fn header_only_counts_over_verified_https() {
+        let mut store = HstsStore::default();
+        let plain = url("http://example.com/");
+        assert!(!store.note(&plain, &response(&plain, "max-age=60", true)));
+        let secure = url("https://example.com/");
+        assert!(!store.note(&secure, &response(&secure, "max-age=60", false)));
+        let mut unknown = response(&secure, "max-age=60", true);
+        unknown.certificate = None;
+        assert!(!store.note(&secure, &unknown));
+        assert_eq!(upgraded(&mut store, "http://example.com/"), None);
+    }
+
+    #[test]
+    fn preload_list_applies_when_enabled() {
+        let mut store = HstsStore::default();
+        assert!(store.upgrade(&url("http://site.dev/"), true).is_some());
+        assert!(store.upgrade(&url("http://site.dev/"), false).is_none());
+        assert!(store
+            .upgrade(&url("http://www.paypal.com/"), true)
+            .is_none());
+    }
+
+    #[test]
+    fn parses_directives() {
+        assert_eq!(parse("max-age=\"60\"; includeSubDomains"), Some((60, true)));
+        assert_eq!(parse("max-age=60; max-age=70"), None);
+        assert_eq!(parse("includeSubDomains"), None);
+    }
+}
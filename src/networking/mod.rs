@@ -1,2 +1,23 @@
-mod header;
+pub mod about;
+pub mod archive;
+pub mod auth;
+mod cache;
+pub mod charset;
+mod chunked;
+mod compression;
+pub mod config;
+pub mod cookie;
+mod data;
+pub mod date;
+pub mod download;
+pub mod error;
+mod file;
+pub mod hsts;
+mod pool;
+pub mod progress;
+pub mod proxy;
+pub mod request;
+pub mod response;
+pub mod tls;
+pub mod transport;
 pub mod url;
@@ -0,0 +1,124 @@
+use crate::networking::config;
+use crate::networking::error::Result;
+use crate::networking::proxy::{Kind, Proxy};
+use crate::networking::transport::{self, TcpTransport, TlsTransport, Transport};
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/** How long an idle connection is kept before it is closed. */
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/** Most idle connections kept for a single scheme+host+port. */
+const MAX_IDLE_PER_HOST: usize = 6;
+
+/** A connection checked out of the pool. Hand it back with `release` once the response is read. */
+pub struct Connection {
+    key: String,
+    /** Whether this socket was reused from an earlier request. */
+    pub reused: bool,
+    pub stream: BufReader<Box<dyn Transport>>,
+}
+
+struct Idle {
+    stream: BufReader<Box<dyn Transport>>,
+    since: Instant,
+}
+
+static POOL: Mutex<Option<HashMap<String, Vec<Idle>>>> = Mutex::new(None);
+
+/**
+ The pool key for a connection. Plain HTTP through an HTTP proxy is spoken to the proxy
+ itself, so those connections are shared by every origin.
+*/
+fn key(secure: bool, host: &str, port: u16, proxy: Option<&Proxy>) -> String {
+    let scheme = if secure { "https" } else { "http" };
+    match proxy {
+        Some(proxy) if proxy.kind == Kind::Http && !secure => format!("via {}", proxy),
+        Some(proxy) => format!("{}://{}:{} via {}", scheme, host, port, proxy),
+        None => format!("{}://{}:{}", scheme, host, port),
+    }
+}
+
+/**
+ Reuse an idle connection to `host:port` if there is one, otherwise open a new one,
+ giving up after `timeout`.
+*/
+pub fn connect(
+    secure: bool,
+    host: &str,
+    port: u16,
+    proxy: Option<&Proxy>,
+    timeout: Option<Duration>,
+) -> Result<Connection> {
+    let key = key(secure, host, port, proxy);
+    if let Some(stream) = take_idle(&key) {
+        return Ok(Connection {
+            key,
+            reused: true,
+            stream,
+        });
+    }
+    open(secure, host, port, proxy, timeout)
+}
+
+/**
+ Open a brand new connection to `host:port`, through `proxy` if given, bypassing the pool.
+ Connecting and any proxy or TLS handshake each get `timeout`.
+*/
+pub fn open(
+    secure: bool,
+    host: &str,
+    port: u16,
+    proxy: Option<&Proxy>,
+    timeout: Option<Duration>,
+) -> Result<Connection> {
+    let key = key(secure, host, port, proxy);
+    let socket = match proxy {
+        Some(proxy) => proxy.connect(host, port, secure, timeout)?,
+        None => transport::open_socket(host, port, timeout)?,
+    };
+    let stream: Box<dyn Transport> = if secure {
+        Box::new(TlsTransport::handshake(
+            socket,
+            host,
+            &config::get().tls,
+            timeout,
+        )?)
+    } else {
+        Box::new(TcpTransport::new(socket))
+    };
+    Ok(Connection {
+        key,
+        reused: false,
+        stream: BufReader::new(stream),
+    })
+}
+
+/** Return a connection whose response was fully read, so a later request can reuse it. */
+pub fn release(connection: Connection) {
+    let mut pool = POOL.lock().unwrap();
+    let idle = pool
+        .get_or_insert_with(HashMap::new)
+        .entry(connection.key)
+        .or_default();
+    if idle.len() < MAX_IDLE_PER_HOST {
+        idle.push(Idle {
+            stream: connection.stream,
+            since: Instant::now(),
+        });
+    }
+}
+
+/** Drop connections idle for longer than the timeout, then take the newest one for `key`. */
+fn take_idle(key: &str) -> Option<BufReader<Box<dyn Transport>>> {
+    let mut pool = POOL.lock().unwrap();
+    let pool = pool.get_or_insert_with(HashMap::new);
+    for idle in pool.values_mut() {
+        idle.retain(|conn| conn.since.elapsed() < IDLE_TIMEOUT);
+    }
+    pool.retain(|_, idle| !idle.is_empty());
+    pool.get_mut(key)
+        .and_then(|idle| idle.pop())
+        .map(|conn| conn.stream)
+}
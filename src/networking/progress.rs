@@ -0,0 +1,229 @@
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::response::HeaderMap;
+use crate::networking::url::URL;
+use std::cell::RefCell;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/** What a page load is busy with. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Connecting,
+    /** The request is sent and the response has not started. */
+    Waiting,
+    /** Body bytes are arriving. */
+    Receiving,
+    Parsing,
+}
+
+/** A snapshot of how far the current load has got. */
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub phase: Phase,
+    /** Body bytes received so far for the current response, as sent on the wire. */
+    pub received: usize,
+    /** The body size announced by `Content-Length`, if there was one. */
+    pub content_length: Option<usize>,
+}
+
+/** A response as it arrives, for showing it before it is complete. */
+pub enum Stream<'a> {
+    /** A new response from this URL has started, with this status and these headers. */
+    Start(&'a URL, u16, &'a HeaderMap),
+    /** The next piece of its body, with any `Content-Encoding` already removed. */
+    Data(&'a [u8]),
+}
+
+/** Where the responses of a watched thread are streamed to. */
+type Sink = Box<dyn FnMut(Stream)>;
+
+struct Watcher {
+    progress: Progress,
+    report: Box<dyn Fn(Progress)>,
+    cancelled: Arc<AtomicBool>,
+    stream: Option<Sink>,
+    /** Whether the body of the current response goes only to the stream. */
+    diverted: bool,
+}
+
+thread_local! {
+    static WATCHER: RefCell<Option<Watcher>> = const { RefCell::new(None) };
+}
+
+/**
+ Report the progress of every request made on this thread to `report`, and abandon
+ them with `NetworkError::Cancelled` once `cancelled` is set.
+*/
+pub fn watch(report: impl Fn(Progress) + 'static, cancelled: Arc<AtomicBool>) {
+    let watcher = Watcher {
+        progress: Progress {
+            phase: Phase::Connecting,
+            received: 0,
+            content_length: None,
+        },
+        report: Box::new(report),
+        cancelled,
+        stream: None,
+        diverted: false,
+    };
+    WATCHER.with(|w| *w.borrow_mut() = Some(watcher));
+}
+
+/**
+ Also hand every response received on this thread to `stream` piece by piece, including
+ redirects and error pages. Does nothing unless `watch` was called first.
+*/
+pub fn stream(stream: impl FnMut(Stream) + 'static) {
+    WATCHER.with(|w| {
+        if let Some(watcher) = w.borrow_mut().as_mut() {
+            watcher.stream = Some(Box::new(stream));
+        }
+    });
+}
+
+fn send(piece: Stream) {
+    // The sink is taken out while it runs, so it can call back into this module.
+    let taken = WATCHER.with(|w| w.borrow_mut().as_mut().and_then(|w| w.stream.take()));
+    let Some(mut stream) = taken else {
+        return;
+    };
+    stream(piece);
+    WATCHER.with(|w| {
+        if let Some(watcher) = w.borrow_mut().as_mut() {
+            watcher.stream.get_or_insert(stream);
+        }
+    });
+}
+
+/**
+ Keep the body of the response that is starting out of memory: it only goes to the stream,
+ and the response is left with an empty body. For sinks to call on `Stream::Start`.
+*/
+pub fn divert() {
+    WATCHER.with(|w| {
+        if let Some(watcher) = w.borrow_mut().as_mut() {
+            watcher.diverted = true;
+        }
+    });
+}
+
+/** Whether the body of the latest response on this thread was diverted. */
+pub fn diverted() -> bool {
+    WATCHER.with(|w| w.borrow().as_ref().is_some_and(|watcher| watcher.diverted))
+}
+
+/** Fail with `NetworkError::Cancelled` if the load was cancelled, without reporting anything. */
+fn check() -> io::Result<()> {
+    WATCHER.with(|w| match w.borrow().as_ref() {
+        Some(watcher) if watcher.cancelled.load(Ordering::Relaxed) => {
+            Err(io::Error::other(NetworkError::Cancelled))
+        }
+        _ => Ok(()),
+    })
+}
+
+/** Apply `update` to the current progress and report it. Does nothing if nobody watches. */
+fn update(update: impl FnOnce(&mut Progress)) -> Result<()> {
+    WATCHER.with(|w| {
+        let mut w = w.borrow_mut();
+        let Some(watcher) = w.as_mut() else {
+            return Ok(());
+        };
+        if watcher.cancelled.load(Ordering::Relaxed) {
+            return Err(NetworkError::Cancelled);
+        }
+        update(&mut watcher.progress);
+        (watcher.report)(watcher.progress);
+        Ok(())
+    })
+}
+
+/** Move on to `phase`, failing if the load was cancelled meanwhile. */
+pub fn phase(phase: Phase) -> Result<()> {
+    update(|progress| {
+        progress.phase = phase;
+        // A new connection means a new request, perhaps after a redirect.
+        if phase == Phase::Connecting {
+            progress.received = 0;
+            progress.content_length = None;
+        }
+    })
+}
+
+/**
+ A response from `url` with `headers` and a body of `content_length` bytes, if known, is
+ starting.
+*/
+pub fn receiving(
+    url: &URL,
+    status: u16,
+    headers: &HeaderMap,
+    content_length: Option<usize>,
+) -> Result<()> {
+    update(|progress| {
+        progress.phase = Phase::Receiving;
+        progress.received = 0;
+        progress.content_length = content_length;
+    })?;
+    WATCHER.with(|w| {
+        if let Some(watcher) = w.borrow_mut().as_mut() {
+            watcher.diverted = false;
+        }
+    });
+    send(Stream::Start(url, status, headers));
+    Ok(())
+}
+
+fn received(bytes: usize) -> io::Result<()> {
+    update(|progress| {
+        if progress.phase == Phase::Receiving {
+            progress.received += bytes;
+        }
+    })
+    .map_err(io::Error::other)
+}
+
+/** Wraps the reader a response is parsed from, counting bytes as they are consumed. */
+pub struct Reader<R>(pub R);
+
+impl<R: BufRead> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        received(n)?;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        check()?;
+        self.0.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt);
+        // Cancellation is noticed on the next `fill_buf`; `consume` cannot fail.
+        let _ = received(amt);
+    }
+}
+
+/**
+ Collects a decoded response body, handing each piece to the stream as it is written.
+ A diverted body is only handed on.
+*/
+pub struct Writer<W>(pub W);
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = if diverted() {
+            buf.len()
+        } else {
+            self.0.write(buf)?
+        };
+        send(Stream::Data(&buf[..n]));
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
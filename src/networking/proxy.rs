@@ -0,0 +1,434 @@
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::request::{Method, Request};
+use crate::networking::response::Response;
+use crate::networking::transport;
+use crate::networking::url::{percent_decode, URL};
+use base64::prelude::*;
+use std::env;
+use std::fmt;
+use std::io::{BufReader, Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::time::Duration;
+
+/** The protocol spoken to a proxy. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /** An HTTP proxy: absolute-form requests for `http`, `CONNECT` tunnels for `https`. */
+    Http,
+    /** A SOCKS5 proxy (RFC 1928). Host names are resolved by the proxy. */
+    Socks5,
+}
+
+/**
+ `value` after its length in one byte, the way SOCKS5 sends names and credentials. Longer
+ values cannot be sent at all.
+*/
+fn length_prefixed(value: &str, what: &str) -> Result<Vec<u8>> {
+    let len = u8::try_from(value.len())
+        .map_err(|_| NetworkError::Proxy(format!("SOCKS5: {} is longer than 255 bytes", what)))?;
+    let mut bytes = vec![len];
+    bytes.extend_from_slice(value.as_bytes());
+    Ok(bytes)
+}
+
+/** A proxy server and the credentials to give it, if any. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proxy {
+    pub kind: Kind,
+    pub host: String,
+    pub port: u16,
+    username: String,
+    password: String,
+}
+
+impl Proxy {
+    /**
+     Parse `http://host:port`, `socks5://host:port` or `socks5h://host:port`, optionally
+     with `user:pass@`. A bare `host:port` is an HTTP proxy.
+    */
+    pub fn parse(input: &str) -> Result<Self> {
+        let invalid = || NetworkError::InvalidUrl(format!("bad proxy {:?}", input));
+        let (scheme, rest) = input.split_once("://").unwrap_or(("http", input));
+        let (kind, default_port) = match scheme.to_lowercase().as_str() {
+            "http" => (Kind::Http, 80),
+            "socks5" | "socks5h" => (Kind::Socks5, 1080),
+            _ => return Err(invalid()),
+        };
+        let authority = rest.split('/').next().unwrap_or_default();
+        let (userinfo, hostport) = match authority.rsplit_once('@') {
+            Some((userinfo, hostport)) => (userinfo, hostport),
+            None => ("", authority),
+        };
+        let (username, password) = userinfo.split_once(':').unwrap_or((userinfo, ""));
+        // The port follows the last colon, unless that colon is inside an IPv6 literal.
+        let (host, port) = match hostport.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| invalid())?)
+            }
+            _ => (hostport, default_port),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            kind,
+            host: host.to_lowercase(),
+            port,
+            username: percent_decode(username),
+            password: percent_decode(password),
+        })
+    }
+
+    /** The `Proxy-Authorization` value for an HTTP proxy with credentials. */
+    pub fn authorization(&self) -> Option<String> {
+        if self.kind != Kind::Http || self.username.is_empty() {
+            return None;
+        }
+        let credentials = format!("{}:{}", self.username, self.password);
+        Some(format!("Basic {}", BASE64_STANDARD.encode(credentials)))
+    }
+
+    /**
+     Open a socket through this proxy that carries traffic for `host:port`.
+     Plain HTTP through an HTTP proxy talks to the proxy itself; anything else is tunneled.
+    */
+    pub fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        secure: bool,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream> {
+        let mut socket = transport::open_socket(&self.host, self.port, timeout)?;
+        socket.set_read_timeout(timeout)?;
+        socket.set_write_timeout(timeout)?;
+        match self.kind {
+            Kind::Http if !secure => {}
+            Kind::Http => self.tunnel(&mut socket, host, port)?,
+            Kind::Socks5 => self.socks5(&mut socket, host, port)?,
+        }
+        Ok(socket)
+    }
+
+    /** Ask an HTTP proxy to open a `CONNECT` tunnel to `host:port`. */
+    fn tunnel(&self, socket: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        let target = format!("{}:{}", host, port);
+        let mut request = Request::new(Method::Connect, &target, &target);
+        if let Some(authorization) = self.authorization() {
+            request = request.header("Proxy-Authorization", &authorization);
+        }
+        socket.write_all(&request.to_bytes())?;
+        // The proxy sends nothing after its response until the tunnel is used, so nothing
+        // meant for the TLS handshake can end up in this reader's buffer.
+        let url = URL::new(&format!("https://{}", target))?;
+        let (response, _) = Response::read(&url, Method::Connect, &mut BufReader::new(&*socket))?;
+        if !response.is_success() {
+            return Err(NetworkError::Proxy(format!(
+                "CONNECT {} refused: {} {}",
+                target, response.status, response.reason
+            )));
+        }
+        Ok(())
+    }
+
+    /** Negotiate a SOCKS5 connection to `host:port`, with username/password auth if set. */
+    fn socks5(&self, socket: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        let refused = |reason: &str| NetworkError::Proxy(format!("SOCKS5: {}", reason));
+        let with_password = !self.username.is_empty();
+        if with_password {
+            socket.write_all(&[5, 2, 0, 2])?;
+        } else {
+            socket.write_all(&[5, 1, 0])?;
+        }
+        let mut reply = [0; 2];
+        socket.read_exact(&mut reply)?;
+        match reply {
+            [5, 0] => {}
+            [5, 2] if with_password => {
+                // RFC 1929 username/password subnegotiation.
+                let mut auth = vec![1];
+                auth.extend(length_prefixed(&self.username, "the user name")?);
+                auth.extend(length_prefixed(&self.password, "the password")?);
+                socket.write_all(&auth)?;
+                socket.read_exact(&mut reply)?;
+                if reply[1] != 0 {
+                    return Err(refused("username or password rejected"));
+                }
+            }
+            [5, _] => return Err(refused("no acceptable authentication method")),
+            _ => return Err(refused("not a SOCKS5 server")),
+        }
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let mut connect = vec![5, 1, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                connect.push(1);
+                connect.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                connect.push(4);
+                connect.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                connect.push(3);
+                connect.extend(length_prefixed(host, "the host name")?);
+            }
+        }
+        connect.extend_from_slice(&port.to_be_bytes());
+        socket.write_all(&connect)?;
+
+        let mut head = [0; 4];
+        socket.read_exact(&mut head)?;
+        if head[1] != 0 {
+            let reason = match head[1] {
+                1 => "general failure",
+                2 => "connection not allowed by ruleset",
+                3 => "network unreachable",
+                4 => "host unreachable",
+                5 => "connection refused",
+                6 => "TTL expired",
+                7 => "command not supported",
+                8 => "address type not supported",
+                _ => "unknown error",
+            };
+            return Err(refused(reason));
+        }
+        // Skip the bound address the proxy reports, then the two-byte port.
+        let address_len = match head[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0; 1];
+                socket.read_exact(&mut len)?;
+                len[0] as usize
+            }
+            _ => return Err(refused("malformed reply")),
+        };
+        let mut bound = vec![0; address_len + 2];
+        socket.read_exact(&mut bound)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.kind {
+            Kind::Http => "http",
+            Kind::Socks5 => "socks5",
+        };
+        write!(f, "{}://{}:{}", scheme, self.host, self.port)
+    }
+}
+
+/** Which proxy to use for each scheme, and the hosts that are reached directly. */
+#[derive(Debug, Clone, Default)]
+pub struct ProxySettings {
+    pub http: Option<Proxy>,
+    pub https: Option<Proxy>,
+    /** `NO_PROXY` entries: host names, domain suffixes, or `*` for everything. */
+    pub no_proxy: Vec<String>,
+}
+
+/** The first of the given environment variables that is set and not empty. */
+fn var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+impl ProxySettings {
+    /**
+     Read `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`, in either case.
+     Values that do not parse are reported and ignored.
+    */
+    pub fn from_env() -> Self {
+        let proxy = |names: &[&str]| {
+            let value = var(names)?;
+            Proxy::parse(&value)
+                .map_err(|e| eprintln!("Ignoring proxy setting: {}", e))
+                .ok()
+        };
+        let all = proxy(&["ALL_PROXY", "all_proxy"]);
+        Self {
+            http: proxy(&["http_proxy", "HTTP_PROXY"]).or_else(|| all.clone()),
+            https: proxy(&["https_proxy", "HTTPS_PROXY"]).or(all),
+            no_proxy: var(&["no_proxy", "NO_PROXY"])
+                .unwrap_or_default()
+                .split(',')
+                .map(|entry| entry.trim().to_lowercase())
+                .filter(|entry| !entry.is_empty())
+                .collect(),
+        }
+    }
+
+    /** Whether `host` is listed in `NO_PROXY`. */
+    fn bypass(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.no_proxy.iter().any(|entry| {
+            // Entries may carry a port, which is ignored, unless they are bare IPv6 addresses.
+            let entry = match entry.rsplit_once(':') {
+                Some((name, port))
+                    if !name.contains(':') && port.chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    name
+                }
+                _ => entry.as_str(),
+            };
+            let entry = entry
+                .trim_start_matches('.')
+                .trim_start_matches('[')
+                .trim_end_matches(']');
+            entry == "*"
+                || host == entry
+                || (host.ends_with(entry) && host[..host.len() - entry.len()].ends_with('.'))
+        })
+    }
+
+    /** The proxy to reach `url` through, if any. */
+    pub fn select(&self, url: &URL) -> Option<&Proxy> {
+        let proxy = match url.scheme.as_str() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        };
+        proxy.filter(|_| !self.bypass(&url.host))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /**
+     A loopback proxy that runs `serve` on the first connection and then answers `hello`
+     through the tunnel with `tunneled`. Gives back the bytes `serve` read.
+    */
+    fn proxy_server(
+        proxy: &str,
+        serve: impl FnOnce(&mut BufReader<TcpStream>) -> Vec<u8> + Send + 'static,
+    ) -> (Proxy, JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = BufReader::new(stream);
+            let received = serve(&mut stream);
+            // Like a TLS server, the far end waits for the client to speak first.
+            if stream.read_exact(&mut [0; 5]).is_ok() {
+                let _ = stream.get_mut().write_all(b"tunneled");
+            }
+            received
+        });
+        let proxy = Proxy::parse(&format!("{}@127.0.0.1:{}", proxy, port)).unwrap();
+        (proxy, server)
+    }
+
+    /** Read `n` bytes the client sent. */
+    fn take(stream: &mut BufReader<TcpStream>, n: usize) -> Vec<u8> {
+        let mut bytes = vec![0; n];
+        stream.read_exact(&mut bytes).unwrap();
+        bytes
+    }
+
+    /** Say `hello` through the tunnel and read the answer until the proxy hangs up. */
+    fn tunneled(mut socket: TcpStream) -> String {
+        socket.write_all(b"hello").unwrap();
+        let mut text = String::new();
+        socket.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn connect_opens_a_tunnel() {
+        let (proxy, server) = proxy_server("http://u:p", |stream| {
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                stream.read_line(&mut head).unwrap();
+            }
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .unwrap();
+            head.into_bytes()
+        });
+        let socket = proxy.connect("example.com", 443, true, None).unwrap();
+        assert_eq!(tunneled(socket), "tunneled");
+        let head = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(head.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+        assert!(head.contains("Host: example.com:443\r\n"));
+        assert!(head.contains("Proxy-Authorization: Basic dTpw\r\n"));
+    }
+
+    #[test]
+    fn refused_connect_is_a_proxy_error() {
+        let (proxy, _server) = proxy_server("http://u:p", |stream| {
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                stream.read_line(&mut line).unwrap();
+            }
+            let refusal =
+                b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n";
+            stream.get_mut().write_all(refusal).unwrap();
+            Vec::new()
+        });
+        let result = proxy.connect("example.com", 443, true, None);
+        assert!(matches!(result, Err(NetworkError::Proxy(_))));
+    }
+
+    #[test]
+    fn socks5_handshake_with_password() {
+        let (proxy, server) = proxy_server("socks5://user:secret", |stream| {
+            let mut received = take(stream, 4);
+            stream.get_mut().write_all(&[5, 2]).unwrap();
+            received.extend(take(stream, 1 + 1 + 4 + 1 + 6));
+            stream.get_mut().write_all(&[1, 0]).unwrap();
+            received.extend(take(stream, 4 + 1 + 11 + 2));
+            // Bound to 10.0.0.1:1080.
+            let reply = [5, 0, 0, 1, 10, 0, 0, 1, 0x04, 0x38];
+            stream.get_mut().write_all(&reply).unwrap();
+            received
+        });
+        let socket = proxy.connect("example.com", 443, true, None).unwrap();
+        assert_eq!(tunneled(socket), "tunneled");
+        let mut expected = vec![5, 2, 0, 2];
+        expected.extend(b"\x01\x04user\x06secret");
+        expected.extend(b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+        assert_eq!(server.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn socks5_refuses_names_longer_than_255_bytes() {
+        let (proxy, _server) = proxy_server("socks5://user", |stream| {
+            let received = take(stream, 3);
+            stream.get_mut().write_all(&[5, 0]).unwrap();
+            received
+        });
+        let host = format!("{}.example.com", "a".repeat(250));
+        let result = proxy.connect(&host, 443, true, None);
+        let Err(NetworkError::Proxy(message)) = result else {
+            panic!("expected a proxy error, got {:?}", result);
+        };
+        assert!(message.contains("host name is longer than 255"));
+    }
+
+    #[test]
+    fn socks5_refuses_credentials_longer_than_255_bytes() {
+        let password = "p".repeat(256);
+        let (proxy, _server) = proxy_server(&format!("socks5://user:{}", password), |stream| {
+            let received = take(stream, 4);
+            stream.get_mut().write_all(&[5, 2]).unwrap();
+            received
+        });
+        let result = proxy.connect("example.com", 443, true, None);
+        let Err(NetworkError::Proxy(message)) = result else {
+            panic!("expected a proxy error, got {:?}", result);
+        };
+        assert!(message.contains("password is longer than 255"));
+    }
+}
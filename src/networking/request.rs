@@ -0,0 +1,228 @@
+use crate::networking::cookie;
+use crate::networking::url::URL;
+use std::fmt;
+
+/** The request methods the browser knows how to send. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    /** Asks an HTTP proxy to open a tunnel; never sent to an origin server. */
+    Connect,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+        }
+    }
+    /** Parse a method name, ignoring case. */
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "GET" => Some(Method::Get),
+            "HEAD" => Some(Method::Head),
+            "POST" => Some(Method::Post),
+            "PUT" => Some(Method::Put),
+            "DELETE" => Some(Method::Delete),
+            _ => None,
+        }
+    }
+    /**
+     Whether sending a request with this method twice has the same effect as sending it
+     once (RFC 9110 section 9.2.2), so it is safe to retry.
+    */
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::Get | Method::Head | Method::Put | Method::Delete
+        )
+    }
+    /** Whether requests with this method are expected to carry a body. */
+    fn has_body(&self) -> bool {
+        matches!(self, Method::Post | Method::Put)
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+/** A request payload together with its media type. */
+#[derive(Debug, Clone)]
+pub struct Body {
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/**
+ An HTTP/1.x request, built up one header at a time.
+ Headers keep their order and may repeat; `Content-Length` is filled in from the body.
+*/
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    target: String,
+    version: Version,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+}
+
+impl Request {
+    /** A request for `target`, starting with the `Host` header. */
+    pub fn new(method: Method, target: &str, host: &str) -> Request {
+        Request {
+            method,
+            target: target.to_owned(),
+            version: Version::Http11,
+            headers: vec![("Host".to_owned(), host.to_owned())],
+            body: None,
+        }
+    }
+    pub fn version(mut self, version: Version) -> Request {
+        self.version = version;
+        self
+    }
+    /** Append a header line. Adding the same key twice sends it twice. */
+    pub fn header(mut self, key: &str, value: &str) -> Request {
+        self.headers.push((key.to_owned(), value.to_owned()));
+        self
+    }
+    /** Replace every value of `key` with `value`, adding the header if it was absent. */
+    pub fn set_header(mut self, key: &str, value: &str) -> Request {
+        self.headers
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+        self.header(key, value)
+    }
+    /** Every value given for `key`, joined with commas, or `None` if the header is absent. */
+    pub fn header_value(&self, key: &str) -> Option<String> {
+        let values: Vec<&str> = self
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+            .collect();
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+    /** Attach a payload, sent with its `Content-Type`. */
+    pub fn body(self, body: &Body) -> Request {
+        let mut request = self.header("Content-Type", &body.content_type);
+        request.body = Some(body.data.clone());
+        request
+    }
+    /** Attach the cookies the jar holds for `url`, if there are any. */
+    pub fn cookies(self, url: &URL, initiator: Option<&URL>) -> Request {
+        match cookie::header_for(url, self.method, initiator) {
+            Some(cookies) => self.header("Cookie", &cookies),
+            None => self,
+        }
+    }
+    /** The path and query, or the whole URL when sent to a proxy, as on the request line. */
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+    /** The full request as sent on the wire: request line, headers, blank line and body. */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.with_body(self.to_string())
+    }
+    /** `to_bytes` with credentials and cookies hidden, for requests that are kept or shared. */
+    pub fn to_redacted_bytes(&self) -> Vec<u8> {
+        self.with_body(self.redacted())
+    }
+    fn with_body(&self, head: String) -> Vec<u8> {
+        let mut bytes = head.into_bytes();
+        if let Some(body) = &self.body {
+            bytes.extend_from_slice(body);
+        }
+        bytes
+    }
+}
+
+/** Headers whose values are secrets, hidden when a request is logged. */
+const SECRET_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
+impl Request {
+    /** The request line and headers, with the values of `SECRET_HEADERS` hidden if `redact`. */
+    fn write_head(&self, f: &mut impl fmt::Write, redact: bool) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}\r\n",
+            self.method,
+            self.target,
+            self.version.as_str()
+        )?;
+        for (key, value) in &self.headers {
+            let secret = redact && SECRET_HEADERS.contains(&key.to_lowercase().as_str());
+            let value = if secret { "[redacted]" } else { value };
+            write!(f, "{}: {}\r\n", key, value)?;
+        }
+        match &self.body {
+            Some(body) => write!(f, "Content-Length: {}\r\n", body.len())?,
+            None if self.method.has_body() => write!(f, "Content-Length: 0\r\n")?,
+            None => {}
+        }
+        write!(f, "\r\n")
+    }
+
+    /** The request line and headers for logging, with credentials and cookies hidden. */
+    pub fn redacted(&self) -> String {
+        let mut head = String::new();
+        let _ = self.write_head(&mut head, true);
+        head
+    }
+}
+
+impl fmt::Display for Request {
+    /** Formats the request line and headers only; the body is left out. */
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_head(f, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_hides_secrets_but_the_wire_keeps_them() {
+        let request = Request::new(Method::Get, "/", "example.com")
+            .header("Authorization", "Basic dTpw")
+            .header("proxy-authorization", "Basic cDpx")
+            .header("Cookie", "session=1")
+            .header("Accept", "text/html");
+        let logged = request.redacted();
+        assert!(
+            !logged.contains("dTpw") && !logged.contains("cDpx") && !logged.contains("session")
+        );
+        assert!(logged.contains("Authorization: [redacted]\r\n"));
+        assert!(logged.contains("Accept: text/html\r\n"));
+        let wire = String::from_utf8(request.to_bytes()).unwrap();
+        assert!(wire.contains("Authorization: Basic dTpw\r\n"));
+        assert!(wire.contains("Cookie: session=1\r\n"));
+    }
+}
@@ -0,0 +1,291 @@
+use crate::networking::charset;
+use crate::networking::chunked;
+use crate::networking::compression;
+use crate::networking::config;
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::request::Method;
+use crate::networking::tls::Certificate;
+use crate::networking::url::URL;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/** Read one CRLF-terminated line, without the terminator. */
+pub fn read_line<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(NetworkError::Io(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed before the end of the response",
+        )));
+    }
+    let line = String::from_utf8_lossy(&line);
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/**
+ Copy the next `len` bytes from `reader` to `out` as they arrive, or everything up to the
+ end of the stream when `len` is `None`.
+*/
+pub fn copy_body<R: BufRead, W: Write>(
+    reader: &mut R,
+    len: Option<usize>,
+    out: &mut W,
+) -> Result<()> {
+    let mut left = len.unwrap_or(usize::MAX);
+    while left > 0 {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            if len.is_none() {
+                break;
+            }
+            return Err(NetworkError::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before the end of the body",
+            )));
+        }
+        let n = buf.len().min(left);
+        out.write_all(&buf[..n])?;
+        reader.consume(n);
+        left -= n;
+    }
+    Ok(())
+}
+
+/** Case-insensitive multimap of response headers. */
+#[derive(Debug, Clone, Default)]
+pub struct HeaderMap {
+    map: HashMap<String, Vec<String>>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /** Append a value for `key`, keeping any earlier values for the same key. */
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.map
+            .entry(key.trim().to_lowercase())
+            .or_default()
+            .push(value.trim().to_owned());
+    }
+    /** First value for `key`, if any. */
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.map
+            .get(&key.to_lowercase())
+            .and_then(|values| values.first())
+            .map(|value| value.as_str())
+    }
+    /** Every value sent for `key`, in the order they were received. */
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.map
+            .get(&key.to_lowercase())
+            .map(|values| values.as_slice())
+            .unwrap_or(&[])
+    }
+    /** Drop every value for `key`. */
+    pub fn remove(&mut self, key: &str) {
+        self.map.remove(&key.to_lowercase());
+    }
+    /** Every header as a lowercase key and one value, with repeated keys yielded once per value. */
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.map.iter().flat_map(|(key, values)| {
+            values
+                .iter()
+                .map(move |value| (key.as_str(), value.as_str()))
+        })
+    }
+    /** Append every value from `other`, such as trailer fields after a chunked body. */
+    pub fn extend(&mut self, other: HeaderMap) {
+        for (key, values) in other.map {
+            self.map.entry(key).or_default().extend(values);
+        }
+    }
+}
+
+/** A parsed HTTP response. */
+#[derive(Debug, Clone)]
+pub struct Response {
+    /** The URL this response came from, after any redirects. */
+    pub url: URL,
+    pub status: u16,
+    pub reason: String,
+    pub headers: HeaderMap,
+    /** Body size as sent by the server, before any `Content-Encoding` was removed. */
+    pub encoded_size: usize,
+    pub body: Vec<u8>,
+    /** Set when the body went to a download as it arrived, leaving `body` empty. */
+    pub diverted: bool,
+    /** The server's certificate, for responses that came over TLS. */
+    pub certificate: Option<Certificate>,
+}
+
+/** The status line and headers of a response, which say how its body is framed. */
+pub struct Head {
+    version: String,
+    method: Method,
+    pub status: u16,
+    reason: String,
+    pub headers: HeaderMap,
+    /** The body size announced by `Content-Length`, if there was one. */
+    pub content_length: Option<usize>,
+}
+
+impl Head {
+    /** Read the status line and headers of a response to a `method` request from `reader`. */
+    pub fn read<R: BufRead>(method: Method, reader: &mut R) -> Result<Self> {
+        let status_line = read_line(reader)?;
+        let mut parts = status_line.splitn(3, ' ');
+        let (version, status) = match (parts.next(), parts.next()) {
+            (Some(version), Some(code)) if version.starts_with("HTTP/") => {
+                (version.to_owned(), code.parse::<u16>().ok())
+            }
+            _ => (String::new(), None),
+        };
+        let status =
+            status.ok_or_else(|| NetworkError::MalformedStatusLine(status_line.clone()))?;
+        let reason = parts.next().unwrap_or_default().to_owned();
+
+        let mut headers = HeaderMap::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            let (k, v) = line
+                .split_once(':')
+                .ok_or_else(|| NetworkError::MalformedHeader(line.clone()))?;
+            headers.insert(k, v);
+        }
+
+        let content_length = match headers.get("content-length") {
+            Some(length) => Some(length.parse::<usize>().map_err(|_| {
+                NetworkError::MalformedHeader(format!("Content-Length: {}", length))
+            })?),
+            None => None,
+        };
+        Ok(Self {
+            version,
+            method,
+            status,
+            reason,
+            headers,
+            content_length,
+        })
+    }
+
+    /**
+     Read the body that follows from `reader`, writing it to `out` as it is decoded, and
+     consuming exactly its bytes. Returns the response from `url` with an empty body, `out`,
+     and whether the connection can carry another request afterwards.
+    */
+    pub fn read_body<R: BufRead, W: Write>(
+        mut self,
+        url: &URL,
+        reader: &mut R,
+        out: W,
+    ) -> Result<(Response, W, bool)> {
+        let connection = self
+            .headers
+            .get("connection")
+            .unwrap_or_default()
+            .to_lowercase();
+        let mut keep_alive = if self.version == "HTTP/1.0" {
+            connection == "keep-alive"
+        } else {
+            connection != "close"
+        };
+        let status = self.status;
+        let no_body = self.method == Method::Head
+            || (self.method == Method::Connect && (200..300).contains(&status))
+            || (100..200).contains(&status)
+            || status == 204
+            || status == 304;
+        let content_encoding = match self.headers.get("content-encoding") {
+            Some(encoding) if !no_body => encoding,
+            _ => "",
+        };
+        let limit = config::get().max_decoded_size;
+        let mut decoder = compression::Decoder::new(content_encoding, out, limit)?;
+        if no_body {
+            // These never carry a body, whatever the headers say.
+        } else if let Some(encoding) = self.headers.get("transfer-encoding") {
+            if !encoding.eq_ignore_ascii_case("chunked") {
+                return Err(NetworkError::UnsupportedEncoding(encoding.to_owned()));
+            }
+            let trailers = chunked::decode(reader, &mut decoder)?;
+            self.headers.extend(trailers);
+        } else if let Some(length) = self.content_length {
+            copy_body(reader, Some(length), &mut decoder)?;
+        } else {
+            // No framing: the body runs until the server closes the connection.
+            copy_body(reader, None, &mut decoder)?;
+            keep_alive = false;
+        }
+
+        let encoded_size = decoder.encoded_size();
+        let out = decoder.finish()?;
+        let response = Response {
+            url: url.clone(),
+            status,
+            reason: self.reason,
+            headers: self.headers,
+            encoded_size,
+            body: Vec::new(),
+            diverted: false,
+            certificate: None,
+        };
+        Ok((response, out, keep_alive))
+    }
+}
+
+impl Response {
+    /**
+     Read one HTTP/1.x response to a `method` request from `reader`, consuming exactly its bytes.
+     Also returns whether the connection can carry another request afterwards.
+    */
+    pub fn read<R: BufRead>(url: &URL, method: Method, reader: &mut R) -> Result<(Self, bool)> {
+        let head = Head::read(method, reader)?;
+        let (mut response, body, keep_alive) = head.read_body(url, reader, Vec::new())?;
+        response.body = body;
+        Ok((response, keep_alive))
+    }
+    /** A `200 OK` response generated locally rather than received from a server. */
+    pub fn local(url: &URL, content_type: &str, body: Vec<u8>) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", content_type);
+        headers.insert("Content-Length", &body.len().to_string());
+        Self {
+            url: url.clone(),
+            status: 200,
+            reason: "OK".to_owned(),
+            headers,
+            encoded_size: body.len(),
+            body,
+            diverted: false,
+            certificate: None,
+        }
+    }
+    /** Body size after decompression. */
+    pub fn decoded_size(&self) -> usize {
+        self.body.len()
+    }
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+    /** The MIME type from `Content-Type`, lowercased and without parameters. */
+    pub fn content_type(&self) -> Option<String> {
+        self.headers.get("content-type").map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase()
+        })
+    }
+    /** The body decoded with its detected character encoding, replacing invalid sequences. */
+    pub fn text(&self) -> String {
+        charset::decode(self.headers.get("content-type"), &self.body)
+    }
+}
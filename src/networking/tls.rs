@@ -0,0 +1,97 @@
+use crate::networking::error::{NetworkError, Result};
+use native_tls::{Identity, TlsConnector};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/** How TLS connections are verified and which client certificate they present. */
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    /** PEM or DER files of root certificates trusted on top of the system ones. */
+    pub extra_roots: Vec<PathBuf>,
+    /** A PKCS#12 archive with the client certificate and key to authenticate with. */
+    pub identity: Option<PathBuf>,
+    pub identity_password: String,
+    /** Accept any certificate for any host name. Only for servers you already trust. */
+    pub insecure: bool,
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path)
+        .map_err(|e| NetworkError::Tls(format!("could not read {}: {}", path.display(), e)))
+}
+
+impl TlsSettings {
+    /** A connector built from these settings. */
+    pub fn connector(&self) -> Result<TlsConnector> {
+        let mut builder = TlsConnector::builder();
+        for path in &self.extra_roots {
+            let bytes = read(path)?;
+            // A PEM bundle may hold several certificates; anything else is a single DER one.
+            let text = String::from_utf8_lossy(&bytes);
+            if text.contains("-----BEGIN CERTIFICATE-----") {
+                for pem in text.split_inclusive("-----END CERTIFICATE-----") {
+                    if pem.contains("-----BEGIN CERTIFICATE-----") {
+                        builder.add_root_certificate(native_tls::Certificate::from_pem(
+                            pem.trim().as_bytes(),
+                        )?);
+                    }
+                }
+            } else {
+                builder.add_root_certificate(native_tls::Certificate::from_der(&bytes)?);
+            }
+        }
+        if let Some(path) = &self.identity {
+            let identity = Identity::from_pkcs12(&read(path)?, &self.identity_password)?;
+            builder.identity(identity);
+        }
+        if self.insecure {
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/** What the server's certificate says about it, for showing to the user. */
+#[derive(Debug, Clone)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+    /** SHA-256 of the DER encoding, as colon-separated uppercase hex. */
+    pub fingerprint: String,
+    /** False when the connection was made in insecure mode, so nothing was checked. */
+    pub verified: bool,
+}
+
+impl Certificate {
+    /** Describe a DER-encoded certificate, or `None` if it does not parse. */
+    pub fn from_der(der: &[u8], verified: bool) -> Option<Self> {
+        let (_, certificate) = X509Certificate::from_der(der).ok()?;
+        let time = |seconds: i64| UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64);
+        let validity = certificate.validity();
+        let fingerprint: Vec<String> = Sha256::digest(der)
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        Some(Self {
+            subject: certificate.subject().to_string(),
+            issuer: certificate.issuer().to_string(),
+            not_before: time(validity.not_before.timestamp()),
+            not_after: time(validity.not_after.timestamp()),
+            fingerprint: fingerprint.join(":"),
+            verified,
+        })
+    }
+
+    /** Whether the certificate is outside its validity period right now. */
+    pub fn is_expired(&self) -> bool {
+        let now = SystemTime::now();
+        now < self.not_before || now > self.not_after
+    }
+}
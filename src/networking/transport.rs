@@ -0,0 +1,671 @@
+use crate::networking::archive;
+use crate::networking::error::{NetworkError, Result};
+use crate::networking::progress::{self, Phase};
+use crate::networking::request::{Method, Request};
+use crate::networking::response::{Head, Response};
+use crate::networking::tls::{Certificate, TlsSettings};
+use crate::networking::url::URL;
+use native_tls::TlsStream;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/**
+ A byte stream that HTTP/1.x is spoken over.
+ Each implementation has its own `connect`; reading and writing come from `Read` and `Write`.
+*/
+pub trait Transport: Read + Write + Send {
+    /**
+     Limit how long any single read or write may wait, and when the current request
+     must be finished. `None` removes a limit.
+    */
+    fn set_timeouts(&mut self, per_read: Option<Duration>, deadline: Option<Instant>);
+    /** Drop the deadline, keeping the limit on each read and write. */
+    fn clear_deadline(&mut self);
+    /** The certificate the server presented, for transports that use TLS. */
+    fn certificate(&self) -> Option<Certificate> {
+        None
+    }
+}
+
+/** The limits a socket enforces on each read and write. */
+#[derive(Debug, Clone, Copy, Default)]
+struct Timeouts {
+    per_read: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl Timeouts {
+    /** Give `socket` whatever is left of both limits, failing once the deadline has passed. */
+    fn apply(&self, socket: &TcpStream) -> io::Result<()> {
+        let timeout = time_left(self.per_read, self.deadline)?;
+        socket.set_read_timeout(timeout)?;
+        socket.set_write_timeout(timeout)
+    }
+}
+
+/**
+ The shorter of `timeout` and the time left before `deadline`, or `None` if neither is set.
+ Fails with `TimedOut` once the deadline has passed.
+*/
+pub fn time_left(
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> io::Result<Option<Duration>> {
+    let Some(deadline) = deadline else {
+        return Ok(timeout);
+    };
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the request took too long",
+        ));
+    }
+    Ok(Some(
+        timeout.map_or(remaining, |timeout| timeout.min(remaining)),
+    ))
+}
+
+/** Report a socket that gave up waiting as a timeout with a readable message. */
+fn stalled<T>(result: io::Result<T>) -> io::Result<T> {
+    result.map_err(|e| match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+            io::Error::new(io::ErrorKind::TimedOut, "the server stopped responding")
+        }
+        _ => e,
+    })
+}
+
+impl Transport for Box<dyn Transport> {
+    fn set_timeouts(&mut self, per_read: Option<Duration>, deadline: Option<Instant>) {
+        (**self).set_timeouts(per_read, deadline)
+    }
+    fn clear_deadline(&mut self) {
+        (**self).clear_deadline()
+    }
+    fn certificate(&self) -> Option<Certificate> {
+        (**self).certificate()
+    }
+}
+
+/** Open a TCP connection to the first address of `host` that answers within `timeout`. */
+pub fn open_socket(host: &str, port: u16, timeout: Option<Duration>) -> Result<TcpStream> {
+    let addrs = (host.trim_start_matches('[').trim_end_matches(']'), port)
+        .to_socket_addrs()
+        .map_err(NetworkError::Connect)?;
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no addresses for host");
+    for addr in addrs {
+        let connected = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        };
+        match connected {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(match last_error.kind() {
+        io::ErrorKind::TimedOut => {
+            NetworkError::Timeout(format!("connecting to {}:{}", host, port))
+        }
+        _ => NetworkError::Connect(last_error),
+    })
+}
+
+/** Plain TCP connection to an origin server. */
+pub struct TcpTransport {
+    stream: TcpStream,
+    timeouts: Timeouts,
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.timeouts.apply(&self.stream)?;
+        stalled(self.stream.read(buf))
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.timeouts.apply(&self.stream)?;
+        stalled(self.stream.write(buf))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn set_timeouts(&mut self, per_read: Option<Duration>, deadline: Option<Instant>) {
+        self.timeouts = Timeouts { per_read, deadline };
+    }
+    fn clear_deadline(&mut self) {
+        self.timeouts.deadline = None;
+    }
+}
+
+/** TLS session over TCP, verified against `host`. */
+pub struct TlsTransport {
+    stream: TlsStream<TcpStream>,
+    timeouts: Timeouts,
+    certificate: Option<Certificate>,
+}
+
+impl TlsTransport {
+    /** Start TLS with `host` over an open `socket` as `settings` say, giving up after `timeout`. */
+    pub fn handshake(
+        socket: TcpStream,
+        host: &str,
+        settings: &TlsSettings,
+        timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let handshake = Timeouts {
+            per_read: timeout,
+            deadline: None,
+        };
+        handshake.apply(&socket)?;
+        let connector = settings.connector()?;
+        let domain = host.trim_start_matches('[').trim_end_matches(']');
+        let stream = connector.connect(domain, socket)?;
+        let certificate = stream
+            .peer_certificate()?
+            .and_then(|certificate| certificate.to_der().ok())
+            .and_then(|der| Certificate::from_der(&der, !settings.insecure));
+        Ok(Self {
+            stream,
+            timeouts: Timeouts::default(),
+            certificate,
+        })
+    }
+}
+
+impl Read for TlsTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.timeouts.apply(self.stream.get_ref())?;
+        stalled(self.stream.read(buf))
+    }
+}
+
+impl Write for TlsTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.timeouts.apply(self.stream.get_ref())?;
+        stalled(self.stream.write(buf))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TlsTransport {
+    fn set_timeouts(&mut self, per_read: Option<Duration>, deadline: Option<Instant>) {
+        self.timeouts = Timeouts { per_read, deadline };
+    }
+    fn clear_deadline(&mut self) {
+        self.timeouts.deadline = None;
+    }
+    fn certificate(&self) -> Option<Certificate> {
+        self.certificate.clone()
+    }
+}
+
+/** Serves canned response bytes and records what was written, without touching the network. */
+pub struct MemoryTransport {
+    input: Cursor<Vec<u8>>,
+    pub written: Vec<u8>,
+}
+
+impl MemoryTransport {
+    pub fn new(response: &[u8]) -> Self {
+        Self {
+            input: Cursor::new(response.to_vec()),
+            written: Vec::new(),
+        }
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn set_timeouts(&mut self, _per_read: Option<Duration>, _deadline: Option<Instant>) {}
+    fn clear_deadline(&mut self) {}
+}
+
+/**
+ Read one response to a `method` request for `url` from `reader`, telling the thread's
+ progress watcher that it has started and streaming its body to it as it is decoded.
+ `diverting` runs before the body is read if the watcher sends it to a download.
+*/
+fn read_watched<R: BufRead>(
+    url: &URL,
+    method: Method,
+    reader: &mut R,
+    diverting: impl FnOnce(&mut R),
+) -> Result<(Response, bool)> {
+    let head = Head::read(method, reader)?;
+    progress::receiving(url, head.status, &head.headers, head.content_length)?;
+    if progress::diverted() {
+        diverting(reader);
+    }
+    let (mut response, progress::Writer(body), keep_alive) =
+        head.read_body(url, reader, progress::Writer(Vec::new()))?;
+    response.body = body;
+    response.diverted = progress::diverted();
+    Ok((response, keep_alive))
+}
+
+/**
+ Write `request` to `stream` and read back one response for `url`.
+ Also returns whether the transport can carry another request afterwards.
+ A body that goes to a download may take as long as it needs, so long as it keeps coming.
+*/
+pub fn exchange<S: Transport>(
+    stream: &mut BufReader<S>,
+    url: &URL,
+    request: &Request,
+) -> Result<(Response, bool)> {
+    stream.get_mut().write_all(&request.to_bytes())?;
+    stream.get_mut().flush()?;
+    progress::phase(Phase::Waiting)?;
+    let mut reader = progress::Reader(stream);
+    if !archive::recording() {
+        return read_watched(url, request.method, &mut reader, |reader| {
+            reader.0.get_mut().clear_deadline()
+        });
+    }
+    let mut tap = archive::Tap::new(reader);
+    let result = read_watched(url, request.method, &mut tap, |tap| {
+        tap.get_mut().0.get_mut().clear_deadline()
+    })?;
+    archive::record(url, request, &tap.bytes);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Send a `method` request over a transport that answers with `bytes`. */
+    fn exchange_with(
+        method: Method,
+        bytes: &[u8],
+    ) -> (BufReader<MemoryTransport>, Result<(Response, bool)>) {
+        let url = URL::new("http://example.com/").unwrap();
+        let request = Request::new(method, "/", "example.com");
+        let mut stream = BufReader::new(MemoryTransport::new(bytes));
+        let result = exchange(&mut stream, &url, &request);
+        (stream, result)
+    }
+
+    #[test]
+    fn writes_the_request() {
+        let (stream, result) = exchange_with(Method::Get, b"HTTP/1.1 204 No Content\r\n\r\n");
+        result.unwrap();
+        let written = String::from_utf8(stream.get_ref().written.clone()).unwrap();
+        assert!(written.starts_with("GET / HTTP/1.1\r\n"));
+        assert!(written.contains("Host: example.com\r\n"));
+        assert!(written.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn reads_a_content_length_body() {
+        let (_, result) = exchange_with(
+            Method::Get,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Type: text/plain\r\n\r\nhello",
+        );
+        let (response, keep_alive) = result.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.reason, "OK");
+        assert_eq!(response.content_type().as_deref(), Some("text/plain"));
+        assert_eq!(response.body, b"hello");
+        assert!(keep_alive);
+    }
+
+    #[test]
+    fn short_content_length_body_is_an_error() {
+        let (_, result) = exchange_with(
+            Method::Get,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello",
+        );
+        assert!(matches!(result, Err(NetworkError::Io(_))));
+    }
+
+    #[test]
+    fn reads_a_chunked_body_with_extensions_and_trailers() {
+        let (mut stream, result) = exchange_with(
+            Method::Get,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5;name=value\r\nhello\r\n\
+              7\r\n, world\r\n\
+              0\r\nX-Checksum: abc\r\n\r\nNEXT",
+        );
+        let (response, keep_alive) = result.unwrap();
+        assert_eq!(response.body, b"hello, world");
+        assert_eq!(response.headers.get("x-checksum"), Some("abc"));
+        assert!(keep_alive);
+        // Nothing past the final CRLF is consumed.
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "NEXT");
+    }
+
+    #[test]
+    fn bad_chunk_size_is_an_error() {
+        let (_, result) = exchange_with(
+            Method::Get,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nhello\r\n0\r\n\r\n",
+        );
+        assert!(matches!(result, Err(NetworkError::MalformedChunk(_))));
+    }
+
+    #[test]
+    fn head_204_and_304_have_no_body() {
+        let cases: [(Method, &[u8]); 3] = [
+            (
+                Method::Head,
+                b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            ),
+            (
+                Method::Get,
+                b"HTTP/1.1 204 No Content\r\nContent-Length: 5\r\n\r\n",
+            ),
+            (
+                Method::Get,
+                b"HTTP/1.1 304 Not Modified\r\nTransfer-Encoding: chunked\r\n\r\n",
+            ),
+        ];
+        for (method, bytes) in cases {
+            let (_, result) = exchange_with(method, bytes);
+            let (response, keep_alive) = result.unwrap();
+            assert!(response.body.is_empty());
+            assert!(keep_alive);
+        }
+    }
+
+    #[test]
+    fn keep_alive_follows_version_and_connection() {
+        let cases: [(&[u8], bool); 4] = [
+            (b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n", true),
+            (
+                b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+                false,
+            ),
+            (b"HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n", false),
+            (
+                b"HTTP/1.0 200 OK\r\nConnection: Keep-Alive\r\nContent-Length: 0\r\n\r\n",
+                true,
+            ),
+        ];
+        for (bytes, expected) in cases {
+            let (_, result) = exchange_with(Method::Get, bytes);
+            assert_eq!(result.unwrap().1, expected);
+        }
+        // A body that runs to the end of the stream leaves nothing to reuse.
+        let (_, result) = exchange_with(Method::Get, b"HTTP/1.1 200 OK\r\n\r\nall of it");
+        let (response, keep_alive) = result.unwrap();
+        assert_eq!(response.body, b"all of it");
+        assert!(!keep_alive);
+    }
+
+    #[test]
+    fn reads_two_responses_from_one_connection() {
+        let url = URL::new("http://example.com/").unwrap();
+        let request = Request::new(Method::Get, "/", "example.com");
+        let mut stream = BufReader::new(MemoryTransport::new(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none\
+              HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo",
+        ));
+        let (first, keep_alive) = exchange(&mut stream, &url, &request).unwrap();
+        assert_eq!(first.body, b"one");
+        assert!(keep_alive);
+        let (second, _) = exchange(&mut stream, &url, &request).unwrap();
+        assert_eq!(second.body, b"two");
+    }
+
+    #[test]
+    fn malformed_status_line_is_an_error() {
+        for bytes in [
+            &b"HTTP/1.1 OK\r\n\r\n"[..],
+            b"<html>\r\n\r\n",
+            b"HTTP/1.1\r\n\r\n",
+        ] {
+            let (_, result) = exchange_with(Method::Get, bytes);
+            assert!(matches!(result, Err(NetworkError::MalformedStatusLine(_))));
+        }
+    }
+
+    #[test]
+    fn malformed_header_is_an_error() {
+        let (_, result) = exchange_with(Method::Get, b"HTTP/1.1 200 OK\r\nno colon here\r\n\r\n");
+        assert!(matches!(result, Err(NetworkError::MalformedHeader(_))));
+        let (_, result) = exchange_with(
+            Method::Get,
+            b"HTTP/1.1 200 OK\r\nContent-Length: five\r\n\r\nhello",
+        );
+        assert!(matches!(result, Err(NetworkError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn truncated_head_is_an_error() {
+        let (_, result) = exchange_with(Method::Get, b"HTTP/1.1 200 OK\r\nContent-Le");
+        assert!(matches!(result, Err(NetworkError::Io(_))));
+    }
+
+    #[test]
+    fn only_exchange_streams_to_the_watcher() {
+        use progress::Stream;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        let bytes = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        progress::watch(|_| {}, Arc::new(AtomicBool::new(false)));
+        progress::stream({
+            let seen = seen.clone();
+            move |piece| match piece {
+                Stream::Start(_, status, _) => seen.borrow_mut().push(status.to_string()),
+                Stream::Data(data) => seen.borrow_mut().push(String::from_utf8_lossy(data).into()),
+            }
+        });
+
+        let url = URL::new("http://example.com/").unwrap();
+        let (response, _) = Response::read(&url, Method::Get, &mut &bytes[..]).unwrap();
+        assert_eq!(response.body, b"hello");
+        assert!(seen.borrow().is_empty());
+
+        let (_, result) = exchange_with(Method::Get, bytes);
+        assert_eq!(result.unwrap().0.body, b"hello");
+        assert_eq!(*seen.borrow(), ["200", "hello"]);
+    }
+
+    /**
+     A loopback server that reads one request and then sends `head` followed by each of
+     `pieces`, waiting `pause` before every piece. Returns a transport connected to it.
+    */
+    fn slow_server(
+        head: &'static str,
+        pieces: &'static [&'static str],
+        pause: Duration,
+    ) -> TcpTransport {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                if stream.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+            }
+            let stream = stream.get_mut();
+            let _ = stream.write_all(head.as_bytes());
+            for piece in pieces {
+                std::thread::sleep(pause);
+                if stream.write_all(piece.as_bytes()).is_err() {
+                    return;
+                }
+            }
+        });
+        TcpTransport::new(TcpStream::connect(address).unwrap())
+    }
+
+    #[test]
+    fn diverted_body_outlives_the_total_deadline() {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        const HEAD: &str = "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n";
+        const PIECES: &[&str] = &["ab", "cd", "ef"];
+        let url = URL::new("http://127.0.0.1/").unwrap();
+        let request = Request::new(Method::Get, "/", "127.0.0.1");
+        for divert in [false, true] {
+            progress::watch(|_| {}, Arc::new(AtomicBool::new(false)));
+            progress::stream(move |piece| {
+                if let progress::Stream::Start(..) = piece {
+                    if divert {
+                        progress::divert();
+                    }
+                }
+            });
+            let mut transport = slow_server(HEAD, PIECES, Duration::from_millis(150));
+            let deadline = Instant::now() + Duration::from_millis(200);
+            transport.set_timeouts(Some(Duration::from_secs(5)), Some(deadline));
+            let result = exchange(&mut BufReader::new(transport), &url, &request);
+            if divert {
+                assert!(result.unwrap().0.diverted);
+            } else {
+                assert!(matches!(result, Err(NetworkError::Timeout(_))));
+            }
+        }
+    }
+
+    #[test]
+    fn diverted_body_still_has_a_read_timeout() {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        progress::watch(|_| {}, Arc::new(AtomicBool::new(false)));
+        progress::stream(|piece| {
+            if let progress::Stream::Start(..) = piece {
+                progress::divert();
+            }
+        });
+        let url = URL::new("http://127.0.0.1/").unwrap();
+        let request = Request::new(Method::Get, "/", "127.0.0.1");
+        let head = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n";
+        let mut transport = slow_server(head, &["ab"], Duration::from_secs(2));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        transport.set_timeouts(Some(Duration::from_millis(100)), Some(deadline));
+        let result = exchange(&mut BufReader::new(transport), &url, &request);
+        assert!(matches!(result, Err(NetworkError::Timeout(_))));
+    }
+
+    #[test]
+    fn connect_timeout_is_a_timeout() {
+        // A listener that never accepts stops answering connections once its queue is full.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut queued = Vec::new();
+        let error = loop {
+            match open_socket("127.0.0.1", port, Some(Duration::from_millis(100))) {
+                Ok(socket) if queued.len() < 4096 => queued.push(socket),
+                Ok(_) => panic!("the listener never stopped accepting"),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(error, NetworkError::Timeout(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn silent_tls_handshake_is_a_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let socket = TcpStream::connect(address).unwrap();
+        let started = Instant::now();
+        let result = TlsTransport::handshake(
+            socket,
+            "localhost",
+            &TlsSettings::default(),
+            Some(Duration::from_millis(100)),
+        );
+        assert!(matches!(result, Err(NetworkError::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+
+    #[test]
+    fn stalled_read_is_a_timeout() {
+        let url = URL::new("http://127.0.0.1/").unwrap();
+        let request = Request::new(Method::Get, "/", "127.0.0.1");
+        let heads: [(&str, &[&str]); 2] = [
+            // Nothing at all after the request.
+            ("", &["HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nab"]),
+            // A head, then a body that stops halfway.
+            ("HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nab", &["cd"]),
+        ];
+        for (head, pieces) in heads {
+            let mut transport = slow_server(head, pieces, Duration::from_secs(2));
+            transport.set_timeouts(Some(Duration::from_millis(100)), None);
+            let result = exchange(&mut BufReader::new(transport), &url, &request);
+            assert!(matches!(result, Err(NetworkError::Timeout(_))));
+        }
+    }
+
+    #[test]
+    fn total_deadline_is_a_timeout() {
+        let url = URL::new("http://127.0.0.1/").unwrap();
+        let request = Request::new(Method::Get, "/", "127.0.0.1");
+        // Every read is quick enough on its own, but the whole response is not.
+        let head = "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n";
+        let mut transport = slow_server(head, &["a", "b", "c", "d"], Duration::from_millis(100));
+        let deadline = Instant::now() + Duration::from_millis(250);
+        transport.set_timeouts(Some(Duration::from_secs(5)), Some(deadline));
+        let result = exchange(&mut BufReader::new(transport), &url, &request);
+        assert!(matches!(result, Err(NetworkError::Timeout(_))));
+
+        // A deadline that has already passed fails before anything is sent.
+        let mut transport = slow_server(head, &["abcd"], Duration::ZERO);
+        transport.set_timeouts(None, Some(Instant::now()));
+        let result = exchange(&mut BufReader::new(transport), &url, &request);
+        assert!(matches!(result, Err(NetworkError::Timeout(_))));
+    }
+
+    #[test]
+    fn time_left_takes_the_shorter_limit() {
+        let second = Duration::from_secs(1);
+        assert_eq!(time_left(None, None).unwrap(), None);
+        assert_eq!(time_left(Some(second), None).unwrap(), Some(second));
+        let soon = Instant::now() + Duration::from_secs(60);
+        assert_eq!(time_left(Some(second), Some(soon)).unwrap(), Some(second));
+        assert!(time_left(None, Some(soon)).unwrap().unwrap() <= Duration::from_secs(60));
+        let passed = time_left(Some(second), Some(Instant::now())).unwrap_err();
+        assert_eq!(passed.kind(), io::ErrorKind::TimedOut);
+    }
+}